@@ -3,12 +3,45 @@ use serde::{
     Serialize,
 };
 
+use std::{
+    borrow::Cow,
+    time::{
+        Duration,
+        Instant,
+        SystemTime,
+        UNIX_EPOCH,
+    },
+};
+
+use crate::{
+    consts::{
+        ACTIVITY_MAX_BUTTONS,
+        ACTIVITY_TEXT_MAX_LEN,
+        ACTIVITY_TEXT_MIN_LEN,
+        ACTIVITY_TEXT_PADDING,
+        ACTIVITY_URL_MAX_LEN,
+        BUTTON_LABEL_MAX_LEN,
+        ELLIPSIS,
+    },
+    errors::ActivityError,
+    overlay::ActivityActionType,
+    user::{
+        User,
+        UserActivity,
+    },
+};
+use unicode_segmentation::UnicodeSegmentation;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Activity<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
-    state: Option<&'a str>,
+    state: Option<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    state_url: Option<Cow<'a, str>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    details: Option<&'a str>,
+    details: Option<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details_url: Option<Cow<'a, str>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     timestamps: Option<Timestamps>,
     assets: Assets<'a>,
@@ -18,20 +51,26 @@ pub struct Activity<'a> {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct Timestamps {
-    pub start: i64,
-    pub end: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<i64>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct Assets<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub large_image: Option<&'a str>,
+    pub large_image: Option<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub large_text: Option<Cow<'a, str>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub large_text: Option<&'a str>,
+    pub large_url: Option<Cow<'a, str>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub small_image: Option<&'a str>,
+    pub small_image: Option<Cow<'a, str>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub small_text: Option<&'a str>,
+    pub small_text: Option<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub small_url: Option<Cow<'a, str>>,
 }
 
 impl<'a> Assets<'a> {
@@ -39,71 +78,620 @@ impl<'a> Assets<'a> {
         Self {
             large_image: None,
             large_text: None,
+            large_url: None,
             small_image: None,
             small_text: None,
+            small_url: None,
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 struct Button<'a> {
-    pub label: &'a str,
-    pub url: &'a str,
+    pub label: Cow<'a, str>,
+    pub url: Cow<'a, str>,
 }
 
 impl<'a> Activity<'a> {
     pub fn new() -> Self {
         Self {
             state: None,
+            state_url: None,
             details: None,
+            details_url: None,
             timestamps: None,
             assets: Assets::new(),
             buttons: Vec::new(),
         }
     }
 
-    pub fn set_state(&mut self, state: &'a str) -> Self {
-        self.state = Some(state);
+    pub fn set_state(&mut self, state: impl Into<Cow<'a, str>>) -> Self {
+        self.state = Some(state.into());
+        self.clone()
+    }
+
+    pub fn set_state_url(&mut self, state_url: impl Into<Cow<'a, str>>) -> Self {
+        self.state_url = Some(state_url.into());
         self.clone()
     }
 
-    pub fn set_details(&mut self, details: &'a str) -> Self {
-        self.details = Some(details);
+    pub fn set_details(&mut self, details: impl Into<Cow<'a, str>>) -> Self {
+        self.details = Some(details.into());
         self.clone()
     }
 
+    pub fn set_details_url(&mut self, details_url: impl Into<Cow<'a, str>>) -> Self {
+        self.details_url = Some(details_url.into());
+        self.clone()
+    }
+
+    /// `start` and `end` are Unix timestamps in milliseconds.
     pub fn set_timestamps(&mut self, start: i64, end: i64) -> Self {
-        self.timestamps = Some(Timestamps { start, end });
+        self.timestamps = Some(Timestamps {
+            start: Some(start),
+            end: Some(end),
+        });
         self.clone()
     }
 
-    pub fn set_large_image(&mut self, large_image: &'a str) -> Self {
-        self.assets.large_image = Some(large_image);
+    /// Shows the time elapsed since `start`, a Unix timestamp in milliseconds.
+    pub fn set_start_millis(&mut self, start: i64) -> Self {
+        self.timestamps_mut().start = Some(start);
         self.clone()
     }
 
-    pub fn set_large_text(&mut self, large_text: &'a str) -> Self {
-        self.assets.large_text = Some(large_text);
+    /// Shows the time remaining until `end`, a Unix timestamp in milliseconds.
+    pub fn set_end_millis(&mut self, end: i64) -> Self {
+        self.timestamps_mut().end = Some(end);
         self.clone()
     }
 
-    pub fn set_small_image(&mut self, small_image: &'a str) -> Self {
-        self.assets.small_image = Some(small_image);
+    pub fn set_start_time(&mut self, start: SystemTime) -> Self {
+        self.set_start_millis(unix_millis(start))
+    }
+
+    pub fn set_end_time(&mut self, end: SystemTime) -> Self {
+        self.set_end_millis(unix_millis(end))
+    }
+
+    /// Starts the elapsed timer as if `elapsed` had already passed. Durations too large to
+    /// represent leave the timestamps unchanged.
+    pub fn set_elapsed(&mut self, elapsed: Duration) -> Self {
+        match SystemTime::now().checked_sub(elapsed) {
+            Some(start) => self.set_start_time(start),
+            None => self.clone(),
+        }
+    }
+
+    /// Shows the time elapsed since `launched`, e.g. an `Instant` taken at startup.
+    pub fn set_elapsed_since(&mut self, launched: Instant) -> Self {
+        self.set_elapsed(launched.elapsed())
+    }
+
+    /// Shows a countdown that reaches zero once `remaining` has passed. Durations too large to
+    /// represent leave the timestamps unchanged.
+    pub fn set_remaining(&mut self, remaining: Duration) -> Self {
+        match SystemTime::now().checked_add(remaining) {
+            Some(end) => self.set_end_time(end),
+            None => self.clone(),
+        }
+    }
+
+    fn timestamps_mut(&mut self) -> &mut Timestamps {
+        self.timestamps.get_or_insert(Timestamps {
+            start: None,
+            end: None,
+        })
+    }
+
+    pub fn set_large_image(&mut self, large_image: impl Into<Cow<'a, str>>) -> Self {
+        self.assets.large_image = Some(large_image.into());
         self.clone()
     }
 
-    pub fn set_small_text(&mut self, small_text: &'a str) -> Self {
-        self.assets.small_text = Some(small_text);
+    pub fn set_large_text(&mut self, large_text: impl Into<Cow<'a, str>>) -> Self {
+        self.assets.large_text = Some(large_text.into());
         self.clone()
     }
 
-    pub fn set_buttons(&mut self, buttons: Vec<(&'a str, &'a str)>) -> Self {
+    pub fn set_large_url(&mut self, large_url: impl Into<Cow<'a, str>>) -> Self {
+        self.assets.large_url = Some(large_url.into());
+        self.clone()
+    }
+
+    pub fn set_small_image(&mut self, small_image: impl Into<Cow<'a, str>>) -> Self {
+        self.assets.small_image = Some(small_image.into());
+        self.clone()
+    }
+
+    pub fn set_small_text(&mut self, small_text: impl Into<Cow<'a, str>>) -> Self {
+        self.assets.small_text = Some(small_text.into());
+        self.clone()
+    }
+
+    pub fn set_small_url(&mut self, small_url: impl Into<Cow<'a, str>>) -> Self {
+        self.assets.small_url = Some(small_url.into());
+        self.clone()
+    }
+
+    /// Adds `buttons` after the ones already set. Discord shows at most two: more are rejected by
+    /// [`Activity::validate`], and so by [`crate::DiscordIpcClient::set_activity`], while
+    /// [`Activity::normalize`] drops them.
+    pub fn set_buttons<L, U>(&mut self, buttons: Vec<(L, U)>) -> Self
+    where
+        L: Into<Cow<'a, str>>,
+        U: Into<Cow<'a, str>>,
+    {
         for button in buttons {
             self.buttons.push(Button {
-                label: button.0,
-                url: button.1,
+                label: button.0.into(),
+                url: button.1.into(),
             })
         }
         self.clone()
     }
+
+    /// Lenient alternative to [`Activity::validate`] for text that can't be controlled, like
+    /// song or file names: trims whitespace, truncates over-long text at a grapheme boundary
+    /// with an ellipsis, pads one character text and drops buttons past the limit. Urls and
+    /// timestamps are left untouched.
+    pub fn normalize(&mut self) -> Self {
+        for text in [
+            &mut self.state,
+            &mut self.details,
+            &mut self.assets.large_text,
+            &mut self.assets.small_text,
+        ] {
+            *text = text.take().and_then(|text| {
+                normalize_text(text, ACTIVITY_TEXT_MIN_LEN, ACTIVITY_TEXT_MAX_LEN)
+            });
+        }
+
+        self.buttons = std::mem::take(&mut self.buttons)
+            .into_iter()
+            .filter_map(|button| {
+                Some(Button {
+                    label: normalize_text(button.label, 1, BUTTON_LABEL_MAX_LEN)?,
+                    url: button.url,
+                })
+            })
+            .take(ACTIVITY_MAX_BUTTONS)
+            .collect();
+        self.clone()
+    }
+
+    /// Checks the activity against the limits Discord enforces on presences, which silently drops
+    /// or rejects anything that breaks them. Every violation is reported, not just the first one.
+    pub fn validate(&self) -> Result<(), Vec<ActivityError>> {
+        let mut errors = Vec::new();
+
+        let texts = [
+            ("state", self.state.as_deref()),
+            ("details", self.details.as_deref()),
+            ("assets.large_text", self.assets.large_text.as_deref()),
+            ("assets.small_text", self.assets.small_text.as_deref()),
+        ];
+        for (field, text) in texts {
+            if let Some(text) = text {
+                let len = text.chars().count();
+                if !(ACTIVITY_TEXT_MIN_LEN..=ACTIVITY_TEXT_MAX_LEN).contains(&len) {
+                    errors.push(ActivityError::InvalidLength {
+                        field: field.to_owned(),
+                        len,
+                        min: ACTIVITY_TEXT_MIN_LEN,
+                        max: ACTIVITY_TEXT_MAX_LEN,
+                    });
+                }
+            }
+        }
+
+        if self.buttons.len() > ACTIVITY_MAX_BUTTONS {
+            errors.push(ActivityError::TooManyButtons(self.buttons.len()));
+        }
+
+        let mut urls = vec![
+            ("state_url".to_owned(), self.state_url.as_deref()),
+            ("details_url".to_owned(), self.details_url.as_deref()),
+            (
+                "assets.large_url".to_owned(),
+                self.assets.large_url.as_deref(),
+            ),
+            (
+                "assets.small_url".to_owned(),
+                self.assets.small_url.as_deref(),
+            ),
+        ];
+        for (i, button) in self.buttons.iter().enumerate() {
+            let len = button.label.chars().count();
+            if len == 0 || len > BUTTON_LABEL_MAX_LEN {
+                errors.push(ActivityError::InvalidLength {
+                    field: format!("buttons[{}].label", i),
+                    len,
+                    min: 1,
+                    max: BUTTON_LABEL_MAX_LEN,
+                });
+            }
+            urls.push((format!("buttons[{}].url", i), Some(&*button.url)));
+        }
+
+        for (field, url) in urls {
+            if let Some(url) = url {
+                let len = url.chars().count();
+                if len > ACTIVITY_URL_MAX_LEN {
+                    errors.push(ActivityError::InvalidLength {
+                        field,
+                        len,
+                        min: 1,
+                        max: ACTIVITY_URL_MAX_LEN,
+                    });
+                } else if !is_http_url(url) {
+                    errors.push(ActivityError::InvalidUrl {
+                        field,
+                        url: url.to_owned(),
+                    });
+                }
+            }
+        }
+
+        if let Some(Timestamps {
+            start: Some(start),
+            end: Some(end),
+        }) = self.timestamps
+        {
+            if end <= start {
+                errors.push(ActivityError::EndBeforeStart { start, end });
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Data of the `ACTIVITY_JOIN_REQUEST` event, sent when someone asks to join the activity.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JoinRequest {
+    pub user: User,
+}
+
+/// Data of the `ACTIVITY_JOIN` and `ACTIVITY_SPECTATE` events (and their `GAME_*` aliases).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ActivitySecret {
+    pub secret: String,
+}
+
+/// Data of the `ACTIVITY_INVITE` event.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ActivityInvite {
+    #[serde(rename = "type")]
+    pub kind: ActivityActionType,
+    pub user: User,
+    #[serde(default)]
+    pub activity: Option<UserActivity>,
+    pub channel_id: String,
+    pub message_id: String,
+}
+
+impl Default for Activity<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns `None` if nothing is left once whitespace is trimmed.
+fn normalize_text(text: Cow<'_, str>, min: usize, max: usize) -> Option<Cow<'_, str>> {
+    let trimmed = text.trim();
+    let len = trimmed.chars().count();
+    if len == 0 {
+        return None;
+    }
+    if len > max {
+        let mut truncated = String::new();
+        let mut truncated_len = 0;
+        for grapheme in trimmed.graphemes(true) {
+            let grapheme_len = grapheme.chars().count();
+            if truncated_len + grapheme_len > max - 1 {
+                break;
+            }
+            truncated.push_str(grapheme);
+            truncated_len += grapheme_len;
+        }
+        let mut truncated = truncated.trim_end().to_owned();
+        if truncated.is_empty() {
+            // Not even the first grapheme fits (e.g. a letter with hundreds of combining marks),
+            // cut it at a char boundary instead.
+            truncated = trimmed.chars().take(max - 1).collect();
+        }
+        truncated.push(ELLIPSIS);
+        Some(Cow::Owned(truncated))
+    } else if len < min {
+        let mut padded = trimmed.to_owned();
+        padded.extend(std::iter::repeat(ACTIVITY_TEXT_PADDING).take(min - len));
+        Some(Cow::Owned(padded))
+    } else if trimmed.len() == text.len() {
+        Some(text)
+    } else {
+        Some(Cow::Owned(trimmed.to_owned()))
+    }
+}
+
+fn unix_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+fn is_http_url(url: &str) -> bool {
+    match url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+    {
+        Some(rest) => {
+            !rest.is_empty() && !rest.starts_with('/') && !rest.contains(char::is_whitespace)
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(len: usize) -> String {
+        "a".repeat(len)
+    }
+
+    fn url(len: usize) -> String {
+        let prefix = "https://example.com/";
+        format!("{}{}", prefix, "a".repeat(len - prefix.len()))
+    }
+
+    fn length_error(field: &str, len: usize, min: usize, max: usize) -> ActivityError {
+        ActivityError::InvalidLength {
+            field: field.to_owned(),
+            len,
+            min,
+            max,
+        }
+    }
+
+    #[test]
+    fn empty_activity_is_valid() {
+        assert_eq!(Activity::new().validate(), Ok(()));
+    }
+
+    #[test]
+    fn text_length_boundaries() {
+        for len in [2, 128] {
+            assert_eq!(Activity::new().set_state(text(len)).validate(), Ok(()));
+        }
+        for len in [1, 129] {
+            assert_eq!(
+                Activity::new().set_state(text(len)).validate(),
+                Err(vec![length_error("state", len, 2, 128)])
+            );
+        }
+    }
+
+    #[test]
+    fn text_length_counts_characters() {
+        assert_eq!(Activity::new().set_details("éé").validate(), Ok(()));
+        assert_eq!(
+            Activity::new().set_large_text("é").validate(),
+            Err(vec![length_error("assets.large_text", 1, 2, 128)])
+        );
+    }
+
+    #[test]
+    fn button_count() {
+        let two = vec![
+            ("One", "https://one.example"),
+            ("Two", "https://two.example"),
+        ];
+        assert_eq!(Activity::new().set_buttons(two.clone()).validate(), Ok(()));
+
+        let mut three = two;
+        three.push(("Three", "https://three.example"));
+        assert_eq!(
+            Activity::new().set_buttons(three).validate(),
+            Err(vec![ActivityError::TooManyButtons(3)])
+        );
+        assert_eq!(
+            ActivityError::TooManyButtons(3).to_string(),
+            "An activity can have at most 2 buttons, got 3"
+        );
+    }
+
+    #[test]
+    fn button_label_length() {
+        let valid = text(32);
+        assert_eq!(
+            Activity::new()
+                .set_buttons(vec![(valid.as_str(), "https://example.com")])
+                .validate(),
+            Ok(())
+        );
+
+        let too_long = text(33);
+        assert_eq!(
+            Activity::new()
+                .set_buttons(vec![
+                    ("", "https://example.com"),
+                    (&too_long, "https://example.com")
+                ])
+                .validate(),
+            Err(vec![
+                length_error("buttons[0].label", 0, 1, 32),
+                length_error("buttons[1].label", 33, 1, 32),
+            ])
+        );
+    }
+
+    #[test]
+    fn url_length() {
+        assert_eq!(Activity::new().set_state_url(url(512)).validate(), Ok(()));
+        assert_eq!(
+            Activity::new().set_state_url(url(513)).validate(),
+            Err(vec![length_error("state_url", 513, 1, 512)])
+        );
+    }
+
+    #[test]
+    fn url_scheme() {
+        for valid in ["https://example.com", "http://example.com/path?q=1"] {
+            assert_eq!(Activity::new().set_details_url(valid).validate(), Ok(()));
+        }
+        for invalid in [
+            "example.com",
+            "ftp://example.com",
+            "https://",
+            "https:///path",
+        ] {
+            assert_eq!(
+                Activity::new().set_details_url(invalid).validate(),
+                Err(vec![ActivityError::InvalidUrl {
+                    field: "details_url".to_owned(),
+                    url: invalid.to_owned(),
+                }])
+            );
+        }
+    }
+
+    #[test]
+    fn end_must_be_after_start() {
+        assert_eq!(Activity::new().set_timestamps(1, 2).validate(), Ok(()));
+        for (start, end) in [(2, 2), (2, 1)] {
+            assert_eq!(
+                Activity::new().set_timestamps(start, end).validate(),
+                Err(vec![ActivityError::EndBeforeStart { start, end }])
+            );
+        }
+    }
+
+    #[test]
+    fn every_violation_is_reported() {
+        let errors = Activity::new()
+            .set_state("a")
+            .set_small_text(text(129))
+            .set_large_url("not a url")
+            .set_buttons(vec![
+                ("One", "https://one.example"),
+                ("Two", "https://two.example"),
+                ("Three", "https://three.example"),
+            ])
+            .set_timestamps(5, 1)
+            .validate()
+            .unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![
+                length_error("state", 1, 2, 128),
+                length_error("assets.small_text", 129, 2, 128),
+                ActivityError::TooManyButtons(3),
+                ActivityError::InvalidUrl {
+                    field: "assets.large_url".to_owned(),
+                    url: "not a url".to_owned(),
+                },
+                ActivityError::EndBeforeStart { start: 5, end: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn normalize_truncates_with_an_ellipsis() {
+        let activity = Activity::new().set_state(text(200)).normalize();
+        let state = activity.state.as_deref().unwrap();
+        assert_eq!(state.chars().count(), 128);
+        assert_eq!(state, format!("{}\u{2026}", text(127)));
+        assert_eq!(activity.validate(), Ok(()));
+    }
+
+    #[test]
+    fn normalize_trims_before_truncating() {
+        let long = format!("{} {}", text(126), text(10));
+        let activity = Activity::new().set_state(long).normalize();
+        assert_eq!(
+            activity.state.as_deref(),
+            Some(format!("{}\u{2026}", text(126)).as_str())
+        );
+    }
+
+    #[test]
+    fn normalize_pads_short_text() {
+        let activity = Activity::new().set_details(" a ").normalize();
+        assert_eq!(activity.details.as_deref(), Some("a\u{200b}"));
+        assert_eq!(activity.validate(), Ok(()));
+    }
+
+    #[test]
+    fn normalize_trims_and_drops_empty_text() {
+        let activity = Activity::new()
+            .set_state("  Playing solo  ")
+            .set_details(" \t ")
+            .normalize();
+        assert_eq!(activity.state.as_deref(), Some("Playing solo"));
+        assert_eq!(activity.details, None);
+    }
+
+    #[test]
+    fn normalize_borrows_text_that_fits() {
+        let activity = Activity::new().set_state("In a match").normalize();
+        assert!(matches!(activity.state, Some(Cow::Borrowed("In a match"))));
+    }
+
+    #[test]
+    fn normalize_keeps_graphemes_whole() {
+        // 7 chars joined into a single grapheme.
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{200D}\u{1F466}";
+        let long = format!("{}{}", text(122), family);
+        let activity = Activity::new().set_state(long).normalize();
+        assert_eq!(
+            activity.state.as_deref(),
+            Some(format!("{}\u{2026}", text(122)).as_str())
+        );
+
+        let label = format!("{}{}", text(26), family);
+        let activity = Activity::new()
+            .set_buttons(vec![(label.as_str(), "https://example.com")])
+            .normalize();
+        assert_eq!(activity.buttons[0].label, format!("{}\u{2026}", text(26)));
+    }
+
+    #[test]
+    fn normalize_splits_graphemes_longer_than_the_limit() {
+        let zalgo = format!("e{}", "\u{0301}".repeat(200));
+        let activity = Activity::new().set_state(zalgo).normalize();
+        assert_eq!(activity.state.as_deref().unwrap().chars().count(), 128);
+        assert_eq!(activity.validate(), Ok(()));
+    }
+
+    #[test]
+    fn normalize_fixes_buttons() {
+        let activity = Activity::new()
+            .set_buttons(vec![
+                (" ", "https://empty.example"),
+                ("One", "https://one.example"),
+                ("Two", "https://two.example"),
+            ])
+            .normalize();
+        let labels: Vec<_> = activity
+            .buttons
+            .iter()
+            .map(|button| button.label.as_ref())
+            .collect();
+        assert_eq!(labels, ["One", "Two"]);
+        assert_eq!(activity.validate(), Ok(()));
+    }
+
+    #[test]
+    fn overflowing_durations_leave_timestamps_unchanged() {
+        let activity = Activity::new()
+            .set_elapsed(Duration::MAX)
+            .set_remaining(Duration::MAX);
+        assert!(activity.timestamps.is_none());
+    }
 }
@@ -0,0 +1,283 @@
+use std::{
+    fs,
+    io::{
+        self,
+        Write,
+    },
+    path::PathBuf,
+    time::{
+        Duration,
+        SystemTime,
+        UNIX_EPOCH,
+    },
+};
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::user::User;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Prompt {
+    Consent,
+    None,
+}
+
+/// Arguments of the `AUTHORIZE` command, the client id is filled in by the client.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AuthorizeOptions {
+    pub scopes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rpc_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<Prompt>,
+}
+
+impl AuthorizeOptions {
+    pub fn new<S: Into<String>>(scopes: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            scopes: scopes.into_iter().map(Into::into).collect(),
+            rpc_token: None,
+            prompt: None,
+        }
+    }
+
+    pub fn set_rpc_token(mut self, rpc_token: impl Into<String>) -> Self {
+        self.rpc_token = Some(rpc_token.into());
+        self
+    }
+
+    pub fn set_prompt(mut self, prompt: Prompt) -> Self {
+        self.prompt = Some(prompt);
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuthorizeResponse {
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Application {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub rpc_origins: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuthenticateResponse {
+    pub application: Application,
+    /// ISO 8601 date at which the access token expires.
+    pub expires: String,
+    pub user: User,
+    pub scopes: Vec<String>,
+}
+
+/// The response of Discord's `/oauth2/token` endpoint.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OAuthToken {
+    pub access_token: String,
+    #[serde(default)]
+    pub token_type: Option<String>,
+    /// Lifetime of the access token in seconds.
+    #[serde(default)]
+    pub expires_in: Option<u64>,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    #[serde(default)]
+    pub scope: Option<String>,
+}
+
+/// Exchanges the code returned by `AUTHORIZE` for an access token.
+///
+/// This needs the application's client secret, so it is left to the app: usually a request to
+/// its own backend, or a mock handing out fake tokens in tests. Closures taking the code
+/// implement it.
+pub trait TokenExchange {
+    fn exchange(&mut self, code: &str) -> Result<OAuthToken, String>;
+}
+
+impl<F> TokenExchange for F
+where
+    F: FnMut(&str) -> Result<OAuthToken, String>,
+{
+    fn exchange(&mut self, code: &str) -> Result<OAuthToken, String> {
+        self(code)
+    }
+}
+
+/// Renews an expired or rejected access token, usually with its refresh token. Closures taking
+/// the current token implement it.
+pub trait TokenRefresh {
+    fn refresh(&mut self, token: &OAuthToken) -> Result<OAuthToken, String>;
+}
+
+impl<F> TokenRefresh for F
+where
+    F: FnMut(&OAuthToken) -> Result<OAuthToken, String>,
+{
+    fn refresh(&mut self, token: &OAuthToken) -> Result<OAuthToken, String> {
+        self(token)
+    }
+}
+
+/// A token along with the Unix time (in seconds) at which it expires.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StoredToken {
+    #[serde(flatten)]
+    pub token: OAuthToken,
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+}
+
+impl StoredToken {
+    pub fn new(token: OAuthToken) -> Self {
+        let expires_at = token.expires_in.map(|expires_in| unix_secs() + expires_in);
+        Self { token, expires_at }
+    }
+
+    pub fn expires_within(&self, margin: Duration) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= unix_secs() + margin.as_secs())
+    }
+}
+
+/// Persists the session token between launches, so users don't have to approve the OAuth prompt
+/// every time.
+pub trait TokenStore {
+    fn load(&mut self) -> io::Result<Option<StoredToken>>;
+    fn save(&mut self, token: &StoredToken) -> io::Result<()>;
+    fn clear(&mut self) -> io::Result<()>;
+}
+
+/// Stores the token as JSON in a single file, readable only by the current user on Unix.
+#[derive(Debug, Clone)]
+pub struct FileTokenStore {
+    path: PathBuf,
+}
+
+impl FileTokenStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl TokenStore for FileTokenStore {
+    fn load(&mut self) -> io::Result<Option<StoredToken>> {
+        match fs::read(&self.path) {
+            // A corrupted file is treated like a missing one, the user just logs in again.
+            Ok(bytes) => match serde_json::from_slice(&bytes) {
+                Ok(token) => Ok(Some(token)),
+                Err(_) => self.clear().map(|_| None),
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn save(&mut self, token: &StoredToken) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Written next to the target and renamed over it, so a crash never leaves half a token.
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+        let _ = fs::remove_file(&temp_path);
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&temp_path)?;
+        file.write_all(&serde_json::to_vec(token)?)?;
+        file.sync_all()?;
+        drop(file);
+
+        fs::rename(&temp_path, &self.path)
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store() -> FileTokenStore {
+        FileTokenStore::new(
+            std::env::temp_dir()
+                .join(format!("kutsrpc-{}", uuid::Uuid::new_v4()))
+                .join("token.json"),
+        )
+    }
+
+    fn token() -> StoredToken {
+        StoredToken {
+            token: OAuthToken {
+                access_token: "access".to_owned(),
+                token_type: None,
+                expires_in: Some(60),
+                refresh_token: Some("refresh".to_owned()),
+                scope: None,
+            },
+            expires_at: Some(1_700_000_060),
+        }
+    }
+
+    #[test]
+    fn file_store_round_trips() {
+        let mut store = temp_store();
+        assert_eq!(store.load().unwrap(), None);
+
+        store.save(&token()).unwrap();
+        assert_eq!(store.load().unwrap(), Some(token()));
+
+        store.clear().unwrap();
+        assert_eq!(store.load().unwrap(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn file_store_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut store = temp_store();
+        store.save(&token()).unwrap();
+        let mode = fs::metadata(&store.path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        store.clear().unwrap();
+    }
+
+    #[test]
+    fn file_store_drops_corrupted_tokens() {
+        let mut store = temp_store();
+        store.save(&token()).unwrap();
+        fs::write(&store.path, b"{ not json").unwrap();
+
+        assert_eq!(store.load().unwrap(), None);
+        assert!(!store.path.exists());
+    }
+}
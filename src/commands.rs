@@ -0,0 +1,101 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+/// The RPC commands understood by the Discord client.
+///
+/// Commands the crate doesn't know yet deserialize into [`Command::Unknown`] and serialize back
+/// to the same string, so payloads keep working when Discord adds new ones.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Command {
+    Dispatch,
+    Authorize,
+    Authenticate,
+    GetGuild,
+    GetGuilds,
+    GetChannel,
+    GetChannels,
+    CreateChannelInvite,
+    GetRelationships,
+    GetUser,
+    Subscribe,
+    Unsubscribe,
+    SetUserVoiceSettings,
+    #[serde(rename = "SET_USER_VOICE_SETTINGS_2")]
+    SetUserVoiceSettings2,
+    SelectVoiceChannel,
+    GetSelectedVoiceChannel,
+    SelectTextChannel,
+    GetVoiceSettings,
+    SetVoiceSettings,
+    #[serde(rename = "SET_VOICE_SETTINGS_2")]
+    SetVoiceSettings2,
+    SetCertifiedDevices,
+    SetActivity,
+    SendActivityJoinInvite,
+    CloseActivityRequest,
+    ActivityInviteUser,
+    AcceptActivityInvite,
+    InviteBrowser,
+    DeepLink,
+    ConnectionsCallback,
+    BraintreePopupBridgeCallback,
+    GiftCodeBrowser,
+    GuildTemplateBrowser,
+    Overlay,
+    BrowserHandoff,
+    GetImage,
+    CaptureShortcut,
+    CreateLobby,
+    UpdateLobby,
+    DeleteLobby,
+    UpdateLobbyMember,
+    ConnectToLobby,
+    DisconnectFromLobby,
+    SendToLobby,
+    SearchLobbies,
+    ConnectToLobbyVoice,
+    DisconnectFromLobbyVoice,
+    SetOverlayLocked,
+    OpenOverlayActivityInvite,
+    OpenOverlayGuildInvite,
+    OpenOverlayVoiceSettings,
+    ValidateApplication,
+    GetEntitlementTicket,
+    GetApplicationTicket,
+    StartPurchase,
+    GetSkus,
+    GetEntitlements,
+    GetNetworkingConfig,
+    NetworkingSystemMetrics,
+    NetworkingPeerMetrics,
+    NetworkingCreateToken,
+    SetUserAchievement,
+    GetUserAchievements,
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_commands_round_trip() {
+        let cmd: Command = serde_json::from_str(r#""SOME_NEW_CMD""#).unwrap();
+        assert_eq!(cmd, Command::Unknown("SOME_NEW_CMD".to_owned()));
+        assert_eq!(serde_json::to_string(&cmd).unwrap(), r#""SOME_NEW_CMD""#);
+    }
+
+    #[test]
+    fn known_commands_keep_their_names() {
+        let cmd: Command = serde_json::from_str(r#""SET_ACTIVITY""#).unwrap();
+        assert_eq!(cmd, Command::SetActivity);
+        assert_eq!(
+            serde_json::to_string(&Command::GetVoiceSettings).unwrap(),
+            r#""GET_VOICE_SETTINGS""#
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 pub(crate) const IPC_PREFIX: &str = "discord-ipc-";
 
 #[cfg(unix)]
@@ -5,3 +7,18 @@ pub(crate) const IPC_DIRS: [&str; 4] = ["XDG_RUNTIME_DIR", "TMPDIR", "TMP", "TEM
 
 #[cfg(windows)]
 pub(crate) const IPC_DIR: &str = r"\\?\pipe\";
+
+pub(crate) const ACTIVITY_TEXT_MIN_LEN: usize = 2;
+pub(crate) const ACTIVITY_TEXT_MAX_LEN: usize = 128;
+pub(crate) const ACTIVITY_URL_MAX_LEN: usize = 512;
+pub(crate) const ACTIVITY_MAX_BUTTONS: usize = 2;
+pub(crate) const BUTTON_LABEL_MAX_LEN: usize = 32;
+pub(crate) const ACTIVITY_TEXT_PADDING: char = '\u{200b}';
+pub(crate) const ELLIPSIS: char = '\u{2026}';
+
+pub(crate) const PRESENCE_RATE_LIMIT: usize = 5;
+pub(crate) const PRESENCE_RATE_WINDOW: Duration = Duration::from_secs(20);
+
+pub(crate) const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60 * 60);
+
+pub(crate) const USER_VOLUME_MAX: f32 = 200.0;
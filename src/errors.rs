@@ -1,3 +1,9 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use serde_json::Value;
+
 #[derive(thiserror::Error, Debug)]
 pub enum PayloadError {
     #[error("Failed to convert the payload into a JSON value: {0}")]
@@ -5,6 +11,9 @@ pub enum PayloadError {
 
     #[error("Invalid payload")]
     ToJsonError,
+
+    #[error("The payload carries no data")]
+    NoDataError,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -22,6 +31,77 @@ pub enum PacketError {
     ReceiveError,
 }
 
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum ActivityError {
+    #[error("{field} is not a valid http(s) url: {url}")]
+    InvalidUrl { field: String, url: String },
+
+    #[error("{field} must be between {min} and {max} characters long, got {len}")]
+    InvalidLength {
+        field: String,
+        len: usize,
+        min: usize,
+        max: usize,
+    },
+
+    #[error(
+        "An activity can have at most {max} buttons, got {0}",
+        max = crate::consts::ACTIVITY_MAX_BUTTONS
+    )]
+    TooManyButtons(usize),
+
+    #[error("timestamps.end ({end}) must be after timestamps.start ({start})")]
+    EndBeforeStart { start: i64, end: i64 },
+}
+
+int_enum! {
+    /// The `code` of an RPC error, see
+    /// <https://discord.com/developers/docs/topics/opcodes-and-status-codes#rpc-rpc-error-codes>.
+    /// Codes this crate doesn't know yet end up in `Other`.
+    pub enum ErrorCode: u32 {
+        UnknownError = 1000,
+        InvalidPayload = 4000,
+        InvalidCommand = 4002,
+        InvalidGuild = 4003,
+        InvalidEvent = 4004,
+        InvalidChannel = 4005,
+        /// Also sent when the command needs an authenticated session.
+        InvalidPermissions = 4006,
+        InvalidClientId = 4007,
+        InvalidOrigin = 4008,
+        InvalidToken = 4009,
+        InvalidUser = 4010,
+        OAuth2Error = 5000,
+        SelectChannelTimedOut = 5001,
+        GetGuildTimedOut = 5002,
+        SelectVoiceForceRequired = 5003,
+        CaptureShortcutAlreadyListening = 5004,
+        _ => Other,
+    }
+}
+
+/// The `data` of an `ERROR` event, sent in response to a command that failed.
+#[derive(thiserror::Error, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[error("Discord rejected the command with code {} ({code:?}): {message}", u32::from(*.code))]
+pub struct RpcError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl RpcError {
+    /// Parses the `data` of an `ERROR` event. Data that doesn't look like an error is kept as the
+    /// message, so the failure isn't reported as a JSON error instead.
+    pub(crate) fn from_data(data: &Value) -> Self {
+        RpcError::deserialize(data).unwrap_or_else(|_| RpcError {
+            code: data["code"]
+                .as_u64()
+                .and_then(|code| u32::try_from(code).ok())
+                .map_or(ErrorCode::UnknownError, ErrorCode::from),
+            message: data.to_string(),
+        })
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum IpcError {
     #[error("Failed to open an ipc connection: {0}")]
@@ -53,6 +133,21 @@ pub enum IpcError {
 
     #[error("Discord Ipc sent a critical error with message: {0}")]
     CriticalError(String),
+
+    #[error("Invalid activity: {}", join_errors(.0))]
+    InvalidActivity(Vec<ActivityError>),
+
+    #[error("Failed to exchange the authorization code for a token: {0}")]
+    TokenExchangeError(String),
+
+    #[error("Failed to refresh the access token: {0}")]
+    TokenRefreshError(String),
+
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
+    #[error("{0}")]
+    Rpc(#[from] RpcError),
 }
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -64,8 +159,77 @@ pub enum Error {
 
     #[error("{0}")]
     Payload(#[from] PayloadError),
+
+    #[error("Invalid activity: {}", join_errors(.0))]
+    Activity(Vec<ActivityError>),
+
+    #[error("{0}")]
+    Rpc(#[from] RpcError),
+}
+
+fn join_errors(errors: &[ActivityError]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 pub(crate) type PayloadResult<T> = Result<T, PayloadError>;
 pub(crate) type PacketResult<T> = Result<T, PacketError>;
 pub(crate) type IpcResult<T> = Result<T, IpcError>;
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn error_codes_round_trip() {
+        for (code, value) in [
+            (ErrorCode::InvalidToken, 4009),
+            (ErrorCode::UnknownError, 1000),
+            (ErrorCode::Other(4242), 4242),
+        ] {
+            assert_eq!(
+                serde_json::from_value::<ErrorCode>(json!(value)).unwrap(),
+                code
+            );
+            assert_eq!(serde_json::to_value(code).unwrap(), json!(value));
+        }
+    }
+
+    #[test]
+    fn rpc_errors_parse() {
+        let error = RpcError::from_data(&json!({ "code": 4006, "message": "Not authenticated" }));
+        assert_eq!(
+            error,
+            RpcError {
+                code: ErrorCode::InvalidPermissions,
+                message: "Not authenticated".to_owned(),
+            }
+        );
+    }
+
+    #[test]
+    fn malformed_rpc_errors_keep_their_data() {
+        let data = json!({ "code": 4011, "reason": "Something new" });
+        assert_eq!(
+            RpcError::from_data(&data),
+            RpcError {
+                code: ErrorCode::Other(4011),
+                message: data.to_string(),
+            }
+        );
+
+        let data = json!("Something went wrong");
+        assert_eq!(
+            RpcError::from_data(&data),
+            RpcError {
+                code: ErrorCode::UnknownError,
+                message: data.to_string(),
+            }
+        );
+    }
+}
@@ -2,24 +2,69 @@ use std::{
     sync::{
         atomic::{
             AtomicBool,
+            AtomicU64,
             Ordering,
         },
         mpsc,
         Arc,
         Mutex,
+        Weak,
     },
     thread,
-    time::Duration,
 };
 
 use serde::{
+    de::DeserializeOwned,
     Deserialize,
     Serialize,
 };
 
-use crate::Payload;
+use serde_json::Value;
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+use crate::{
+    activity::{
+        ActivityInvite,
+        ActivitySecret,
+        JoinRequest,
+    },
+    errors::RpcError,
+    guild::{
+        GuildStatus,
+        PartialChannel,
+        PartialGuild,
+    },
+    lobby::{
+        Lobby,
+        LobbyDelete,
+        LobbyMemberEvent,
+        LobbyMessage,
+    },
+    message::{
+        MessageDelete,
+        MessageEvent,
+        Notification,
+    },
+    overlay::OverlayState,
+    store::{
+        Entitlement,
+        UserAchievement,
+    },
+    user::{
+        Relationship,
+        User,
+    },
+    voice::{
+        ShortcutChange,
+        Speaking,
+        UserVoiceState,
+        VoiceChannelSelect,
+        VoiceConnectionStatus,
+        VoiceSettings,
+    },
+    Payload,
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum Event {
     CurrentUserUpdate,
@@ -32,6 +77,10 @@ pub enum Event {
     VoiceStateDelete,
     VoiceStateUpdate,
     VoiceSettingsUpdate,
+    /// Sent by newer Discord clients alongside `VOICE_SETTINGS_UPDATE`. It isn't documented and
+    /// its data has changed between releases, so it is only delivered as
+    /// [`EventPayload::Raw`].
+    #[serde(rename = "VOICE_SETTINGS_UPDATE_2")]
     VoiceSettingsUpdate2,
     VoiceConnectionStatus,
     SpeakingStart,
@@ -60,53 +109,517 @@ pub enum Event {
     UserAchievementUpdate,
     Ready,
     Error,
+    /// An event the crate doesn't know yet, with its name as sent by Discord.
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+impl Event {
+    /// `READY` and `ERROR` are always dispatched, every other event has to be subscribed to.
+    pub(crate) fn requires_subscription(&self) -> bool {
+        !matches!(self, Event::Ready | Event::Error)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RpcConfig {
+    pub cdn_host: String,
+    pub api_endpoint: String,
+    pub environment: String,
+}
+
+/// Data of the `READY` event, sent once the handshake succeeded.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Ready {
+    pub v: u8,
+    pub config: RpcConfig,
+    #[serde(default)]
+    pub user: Option<User>,
+}
+
+/// The data of an event, deserialized into the model matching its [`Event`].
+///
+/// Events the crate doesn't model, or whose data doesn't match the model, are delivered as
+/// [`EventPayload::Raw`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventPayload {
+    Ready(Ready),
+    CurrentUserUpdate(User),
+    GuildStatus(GuildStatus),
+    GuildCreate(PartialGuild),
+    ChannelCreate(PartialChannel),
+    RelationshipUpdate(Relationship),
+    VoiceChannelSelect(VoiceChannelSelect),
+    VoiceStateCreate(UserVoiceState),
+    VoiceStateDelete(UserVoiceState),
+    VoiceStateUpdate(UserVoiceState),
+    VoiceSettingsUpdate(VoiceSettings),
+    VoiceConnectionStatus(VoiceConnectionStatus),
+    SpeakingStart(Speaking),
+    SpeakingStop(Speaking),
+    GameJoin(ActivitySecret),
+    GameSpectate(ActivitySecret),
+    ActivityJoin(ActivitySecret),
+    ActivityJoinRequest(JoinRequest),
+    ActivitySpectate(ActivitySecret),
+    ActivityInvite(ActivityInvite),
+    NotificationCreate(Notification),
+    MessageCreate(MessageEvent),
+    MessageUpdate(MessageEvent),
+    MessageDelete(MessageDelete),
+    LobbyDelete(LobbyDelete),
+    LobbyUpdate(Lobby),
+    LobbyMemberConnect(LobbyMemberEvent),
+    LobbyMemberDisconnect(LobbyMemberEvent),
+    LobbyMemberUpdate(LobbyMemberEvent),
+    LobbyMessage(LobbyMessage),
+    CaptureShortcutChange(ShortcutChange),
+    Overlay(OverlayState),
+    OverlayUpdate(OverlayState),
+    EntitlementCreate(Entitlement),
+    EntitlementDelete(Entitlement),
+    UserAchievementUpdate(UserAchievement),
+    Error(RpcError),
+    Raw(Event, Value),
+}
+
+impl EventPayload {
+    /// Returns `None` if `payload` isn't an event.
+    pub fn from_payload(payload: &Payload) -> Option<Self> {
+        match payload {
+            Payload::InComingCommand {
+                data,
+                evt: Some(ev),
+                ..
+            } => Some(Self::parse(ev, data)),
+            _ => None,
+        }
+    }
+
+    fn parse(ev: &Event, data: &Value) -> Self {
+        fn typed<T: DeserializeOwned>(
+            data: &Value,
+            variant: fn(T) -> EventPayload,
+        ) -> Option<EventPayload> {
+            T::deserialize(data).ok().map(variant)
+        }
+
+        let payload = match ev {
+            Event::Ready => typed(data, EventPayload::Ready),
+            Event::CurrentUserUpdate => typed(data, EventPayload::CurrentUserUpdate),
+            Event::GuildStatus => typed(data, EventPayload::GuildStatus),
+            Event::GuildCreate => typed(data, EventPayload::GuildCreate),
+            Event::ChannelCreate => typed(data, EventPayload::ChannelCreate),
+            Event::RelationshipUpdate => typed(data, EventPayload::RelationshipUpdate),
+            Event::VoiceChannelSelect => typed(data, EventPayload::VoiceChannelSelect),
+            Event::VoiceStateCreate => typed(data, EventPayload::VoiceStateCreate),
+            Event::VoiceStateDelete => typed(data, EventPayload::VoiceStateDelete),
+            Event::VoiceStateUpdate => typed(data, EventPayload::VoiceStateUpdate),
+            Event::VoiceSettingsUpdate => typed(data, EventPayload::VoiceSettingsUpdate),
+            Event::VoiceConnectionStatus => typed(data, EventPayload::VoiceConnectionStatus),
+            Event::SpeakingStart => typed(data, EventPayload::SpeakingStart),
+            Event::SpeakingStop => typed(data, EventPayload::SpeakingStop),
+            Event::GameJoin => typed(data, EventPayload::GameJoin),
+            Event::GameSpectate => typed(data, EventPayload::GameSpectate),
+            Event::ActivityJoin => typed(data, EventPayload::ActivityJoin),
+            Event::ActivityJoinRequest => typed(data, EventPayload::ActivityJoinRequest),
+            Event::ActivitySpectate => typed(data, EventPayload::ActivitySpectate),
+            Event::ActivityInvite => typed(data, EventPayload::ActivityInvite),
+            Event::NotificationCreate => typed(data, EventPayload::NotificationCreate),
+            Event::MessageCreate => typed(data, EventPayload::MessageCreate),
+            Event::MessageUpdate => typed(data, EventPayload::MessageUpdate),
+            Event::MessageDelete => typed(data, EventPayload::MessageDelete),
+            Event::LobbyDelete => typed(data, EventPayload::LobbyDelete),
+            Event::LobbyUpdate => typed(data, EventPayload::LobbyUpdate),
+            Event::LobbyMemberConnect => typed(data, EventPayload::LobbyMemberConnect),
+            Event::LobbyMemberDisconnect => typed(data, EventPayload::LobbyMemberDisconnect),
+            Event::LobbyMemberUpdate => typed(data, EventPayload::LobbyMemberUpdate),
+            Event::LobbyMessage => typed(data, EventPayload::LobbyMessage),
+            Event::CaptureShortcutChange => typed(data, EventPayload::CaptureShortcutChange),
+            Event::Overlay => typed(data, EventPayload::Overlay),
+            Event::OverlayUpdate => typed(data, EventPayload::OverlayUpdate),
+            Event::EntitlementCreate => typed(data, EventPayload::EntitlementCreate),
+            Event::EntitlementDelete => typed(data, EventPayload::EntitlementDelete),
+            Event::UserAchievementUpdate => typed(data, EventPayload::UserAchievementUpdate),
+            Event::Error => typed(data, EventPayload::Error),
+            Event::VoiceSettingsUpdate2 | Event::Unknown(_) => None,
+        };
+        payload.unwrap_or_else(|| EventPayload::Raw(ev.clone(), data.clone()))
+    }
+}
+
+type Callback = Box<dyn FnMut(Payload) + Send>;
+
+struct Listener {
+    id: u64,
+    /// `None` for catch-all listeners.
+    ev: Option<Event>,
+    /// The arguments of the subscription, used to tell apart subscriptions to the same event.
+    args: Value,
+    /// Sequence number of the first event emitted after the listener was registered. Older events
+    /// may still be waiting for the dispatcher, but they aren't for this listener.
+    since: u64,
+    callback: Arc<Mutex<Callback>>,
+}
+
+impl Listener {
+    fn wants(&self, seq: u64, ev: &Event, payload: &Payload) -> bool {
+        seq >= self.since
+            && self.ev.as_ref().map_or(true, |listening| listening == ev)
+            && matches_args(&self.args, payload)
+    }
+}
+
+/// Whether `payload` belongs to a subscription made with `args`, i.e. every id it was subscribed
+/// with (`channel_id`, `lobby_id`...) has the same value in the event data. Discord leaves the ids
+/// out of some events, like `VOICE_STATE_*`, which then match any subscription to the event.
+fn matches_args(args: &Value, payload: &Payload) -> bool {
+    let (Some(args), Payload::InComingCommand { data, .. }) = (args.as_object(), payload) else {
+        return true;
+    };
+    args.iter()
+        .all(|(key, value)| data.get(key).map_or(true, |found| found == value))
 }
 
 pub struct EventHandler {
-    emitter: mpsc::Sender<(Event, Payload)>,
-    listener: Arc<Mutex<mpsc::Receiver<(Event, Payload)>>>,
+    emitter: mpsc::Sender<(u64, Event, Payload)>,
+    listeners: Arc<Mutex<Vec<Listener>>>,
     running: Arc<AtomicBool>,
+    next_id: AtomicU64,
+    next_seq: AtomicU64,
 }
 
 impl EventHandler {
     pub fn new() -> Self {
-        let (emitter, listener) = mpsc::channel();
+        let (emitter, receiver) = mpsc::channel::<(u64, Event, Payload)>();
+        let listeners = Arc::new(Mutex::new(Vec::<Listener>::new()));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let dispatch_listeners = Arc::clone(&listeners);
+        let dispatch_running = Arc::clone(&running);
+        thread::spawn(move || {
+            while let Ok((seq, ev, payload)) = receiver.recv() {
+                if !dispatch_running.load(Ordering::SeqCst) {
+                    break;
+                }
+                // Callbacks run without the listener list locked, so they can add or drop
+                // subscriptions themselves.
+                let callbacks: Vec<_> = dispatch_listeners
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter(|listener| listener.wants(seq, &ev, &payload))
+                    .map(|listener| Arc::clone(&listener.callback))
+                    .collect();
+                for callback in callbacks {
+                    (callback.lock().unwrap())(payload.clone());
+                }
+            }
+        });
+
         EventHandler {
             emitter,
-            listener: Arc::new(Mutex::new(listener)),
-            running: Arc::new(AtomicBool::new(true)),
+            listeners,
+            running,
+            next_id: AtomicU64::new(0),
+            next_seq: AtomicU64::new(0),
         }
     }
 
     pub fn emit(&self, ev: Event, payload: Payload) {
-        self.emitter.send((ev, payload)).unwrap();
+        // The dispatcher only goes away once the handler is stopped, events after that are
+        // dropped.
+        let seq = self.next_seq.fetch_add(1, Ordering::SeqCst);
+        let _ = self.emitter.send((seq, ev, payload));
     }
 
-    pub fn listen<F>(&self, mut callback: F, ev: Event)
+    /// Registers `callback` for the `ev` events matching the subscription `args` and returns the
+    /// id of the new listener.
+    pub fn listen<F>(&self, callback: F, ev: Event, args: Value) -> u64
     where
         F: FnMut(Payload) + Send + 'static,
     {
-        let listener = Arc::clone(&self.listener);
-        let running = Arc::clone(&self.running);
-        thread::spawn(move || {
-            let listener = listener.lock().unwrap();
-            while running.load(Ordering::SeqCst) {
-                match listener.recv() {
-                    Ok(event) => {
-                        if ev == event.0 {
-                            callback(event.1);
-                            thread::sleep(Duration::from_millis(100))
-                        }
-                    }
-                    Err(_) => {
-                        break;
-                    }
-                }
-            }
+        self.add_listener(Box::new(callback), Some(ev), args)
+    }
+
+    /// Registers `callback` for every event, including the ones the crate doesn't know.
+    pub fn listen_all<F>(&self, callback: F) -> u64
+    where
+        F: FnMut(Payload) + Send + 'static,
+    {
+        self.add_listener(Box::new(callback), None, Value::Null)
+    }
+
+    fn add_listener(&self, callback: Callback, ev: Option<Event>, args: Value) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        self.listeners.lock().unwrap().push(Listener {
+            id,
+            ev,
+            args,
+            since: self.next_seq.load(Ordering::SeqCst),
+            callback: Arc::new(Mutex::new(callback)),
         });
+        id
     }
 
     pub fn stop(&self) {
         self.running.store(false, Ordering::SeqCst);
     }
 }
+
+/// Keeps a subscription made with [`crate::DiscordIpcClient::subscribe`] alive.
+///
+/// Dropping it removes the callback and queues an `UNSUBSCRIBE`, which is sent along with the
+/// next command once no other subscription for the same event and arguments is left.
+#[must_use = "dropping a Subscription removes its callback right away"]
+pub struct Subscription {
+    event: Option<Event>,
+    args: Value,
+    listener_id: u64,
+    listeners: Weak<Mutex<Vec<Listener>>>,
+    unsubscribe: mpsc::Sender<(Event, Value)>,
+}
+
+impl Subscription {
+    pub(crate) fn new(
+        event: Option<Event>,
+        args: Value,
+        listener_id: u64,
+        event_handler: &EventHandler,
+        unsubscribe: mpsc::Sender<(Event, Value)>,
+    ) -> Self {
+        Self {
+            event,
+            args,
+            listener_id,
+            listeners: Arc::downgrade(&event_handler.listeners),
+            unsubscribe,
+        }
+    }
+
+    /// `None` for catch-all subscriptions.
+    pub fn event(&self) -> Option<&Event> {
+        self.event.as_ref()
+    }
+
+    pub fn args(&self) -> &Value {
+        &self.args
+    }
+
+    /// Keeps the callback registered for as long as the client lives.
+    pub fn detach(mut self) {
+        self.event = None;
+        self.listeners = Weak::new();
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(listeners) = self.listeners.upgrade() {
+            listeners
+                .lock()
+                .unwrap()
+                .retain(|listener| listener.id != self.listener_id);
+        }
+        if let Some(event) = self.event.take() {
+            if event.requires_subscription() {
+                let _ = self.unsubscribe.send((event, self.args.take()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Parses a whole frame as sent by Discord.
+    fn parse(frame: Value) -> EventPayload {
+        EventPayload::from_payload(&serde_json::from_value(frame).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn parses_ready() {
+        let payload = parse(json!({
+            "cmd": "DISPATCH",
+            "data": {
+                "v": 1,
+                "config": {
+                    "cdn_host": "cdn.discordapp.com",
+                    "api_endpoint": "//discord.com/api",
+                    "environment": "production",
+                },
+                "user": {
+                    "id": "53908232506183680",
+                    "username": "Mason",
+                    "discriminator": "0",
+                    "global_name": "Mason",
+                    "avatar": "a_bab14f271d565501444b2ca3be944b25",
+                    "premium_type": 0,
+                },
+            },
+            "evt": "READY",
+            "nonce": null,
+        }));
+
+        let EventPayload::Ready(ready) = payload else {
+            panic!("unexpected payload {payload:?}");
+        };
+        assert_eq!(ready.v, 1);
+        assert_eq!(ready.config.environment, "production");
+        assert_eq!(ready.user.unwrap().global_name.as_deref(), Some("Mason"));
+    }
+
+    #[test]
+    fn parses_voice_state_update() {
+        let payload = parse(json!({
+            "cmd": "DISPATCH",
+            "data": {
+                "voice_state": {
+                    "mute": false,
+                    "deaf": false,
+                    "self_mute": true,
+                    "self_deaf": false,
+                    "suppress": false,
+                },
+                "user": {
+                    "id": "190320984123768832",
+                    "username": "test 2",
+                    "discriminator": "7479",
+                    "avatar": "b004ec1740a63ca06ae2e14c5cee11f3",
+                    "bot": false,
+                },
+                "nick": "test user 2",
+                "volume": 110,
+                "mute": false,
+                "pan": { "left": 1.0, "right": 1.0 },
+            },
+            "evt": "VOICE_STATE_UPDATE",
+            "nonce": null,
+        }));
+
+        let EventPayload::VoiceStateUpdate(state) = payload else {
+            panic!("unexpected payload {payload:?}");
+        };
+        assert_eq!(state.user.id, "190320984123768832");
+        assert_eq!(state.nick.as_deref(), Some("test user 2"));
+        assert_eq!(state.volume, Some(110.0));
+        assert!(state.voice_state.self_mute);
+        assert!(!state.voice_state.self_deaf);
+    }
+
+    #[test]
+    fn parses_speaking_start() {
+        let payload = parse(json!({
+            "cmd": "DISPATCH",
+            "data": { "user_id": "190320984123768832" },
+            "evt": "SPEAKING_START",
+            "nonce": null,
+        }));
+
+        assert_eq!(
+            payload,
+            EventPayload::SpeakingStart(Speaking {
+                user_id: "190320984123768832".to_owned(),
+                channel_id: None,
+            })
+        );
+    }
+
+    #[test]
+    fn parses_message_create() {
+        let payload = parse(json!({
+            "cmd": "DISPATCH",
+            "data": {
+                "channel_id": "199737254929760256",
+                "message": {
+                    "id": "199743874640379904",
+                    "blocked": false,
+                    "content": "test",
+                    "content_parsed": [{ "content": "test", "type": "text" }],
+                    "author_color": "#ffffff",
+                    "edited_timestamp": null,
+                    "timestamp": "2016-07-05T04:30:50.776Z",
+                    "tts": false,
+                    "mentions": [],
+                    "mention_roles": [],
+                    "mention_everyone": false,
+                    "embeds": [],
+                    "attachments": [],
+                    "type": 0,
+                    "pinned": false,
+                    "author": {
+                        "id": "190320984123768832",
+                        "username": "test 2",
+                        "discriminator": "7479",
+                        "avatar": "b004ec1740a63ca06ae2e14c5cee11f3",
+                        "bot": false,
+                    },
+                },
+            },
+            "evt": "MESSAGE_CREATE",
+            "nonce": null,
+        }));
+
+        let EventPayload::MessageCreate(event) = payload else {
+            panic!("unexpected payload {payload:?}");
+        };
+        assert_eq!(event.channel_id, "199737254929760256");
+        assert_eq!(event.message.id, "199743874640379904");
+        assert_eq!(event.message.content, "test");
+        assert_eq!(event.message.author.username, "test 2");
+        assert_eq!(
+            event.message.timestamp.as_deref(),
+            Some("2016-07-05T04:30:50.776Z")
+        );
+        assert_eq!(event.message.edited_timestamp, None);
+    }
+
+    #[test]
+    fn parses_activity_join_request() {
+        let payload = parse(json!({
+            "cmd": "DISPATCH",
+            "data": {
+                "user": {
+                    "id": "53908232506183680",
+                    "username": "Mason",
+                    "discriminator": "1337",
+                    "avatar": "a_bab14f271d565501444b2ca3be944b25",
+                },
+            },
+            "evt": "ACTIVITY_JOIN_REQUEST",
+            "nonce": null,
+        }));
+
+        let EventPayload::ActivityJoinRequest(request) = payload else {
+            panic!("unexpected payload {payload:?}");
+        };
+        assert_eq!(request.user.id, "53908232506183680");
+        assert_eq!(request.user.discriminator.as_deref(), Some("1337"));
+    }
+
+    #[test]
+    fn unmodeled_or_mismatched_data_stays_raw() {
+        let data = json!({ "input_mode": { "type": "VOICE_ACTIVITY" } });
+        let payload = parse(json!({
+            "cmd": "DISPATCH",
+            "data": data,
+            "evt": "VOICE_SETTINGS_UPDATE_2",
+            "nonce": null,
+        }));
+        assert_eq!(
+            payload,
+            EventPayload::Raw(Event::VoiceSettingsUpdate2, data.clone())
+        );
+
+        let payload = parse(json!({
+            "cmd": "DISPATCH",
+            "data": data,
+            "evt": "SPEAKING_START",
+            "nonce": null,
+        }));
+        assert_eq!(payload, EventPayload::Raw(Event::SpeakingStart, data));
+    }
+}
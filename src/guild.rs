@@ -0,0 +1,84 @@
+use crate::{
+    message::Message,
+    voice::UserVoiceState,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PartialGuild {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub icon_url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Guild {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub icon_url: Option<String>,
+    #[serde(default)]
+    pub vanity_url_code: Option<String>,
+}
+
+int_enum! {
+    pub enum ChannelType: u8 {
+        GuildText = 0,
+        Dm = 1,
+        GuildVoice = 2,
+        GroupDm = 3,
+        GuildCategory = 4,
+        GuildAnnouncement = 5,
+        AnnouncementThread = 10,
+        PublicThread = 11,
+        PrivateThread = 12,
+        GuildStageVoice = 13,
+        GuildDirectory = 14,
+        GuildForum = 15,
+        GuildMedia = 16,
+        _ => Unknown,
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PartialChannel {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: ChannelType,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Channel {
+    pub id: String,
+    #[serde(default)]
+    pub guild_id: Option<String>,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: ChannelType,
+    #[serde(default)]
+    pub topic: Option<String>,
+    #[serde(default)]
+    pub bitrate: Option<u32>,
+    #[serde(default)]
+    pub user_limit: Option<u32>,
+    #[serde(default)]
+    pub position: Option<i32>,
+    #[serde(default)]
+    pub voice_states: Vec<UserVoiceState>,
+    #[serde(default)]
+    pub messages: Vec<Message>,
+}
+
+/// Data of the `GUILD_STATUS` event.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GuildStatus {
+    pub guild: PartialGuild,
+    /// Deprecated by Discord, always 0.
+    #[serde(default)]
+    pub online: u32,
+}
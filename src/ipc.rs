@@ -1,15 +1,72 @@
-use serde_json::json;
+use serde::de::DeserializeOwned;
+use serde_json::{
+    json,
+    Value,
+};
 
 use crate::{
-    consts::IPC_PREFIX,
+    auth::{
+        AuthenticateResponse,
+        AuthorizeOptions,
+        AuthorizeResponse,
+        OAuthToken,
+        StoredToken,
+        TokenExchange,
+        TokenRefresh,
+        TokenStore,
+    },
+    commands::Command,
+    consts::{
+        IPC_PREFIX,
+        TOKEN_REFRESH_MARGIN,
+    },
     errors::{
+        ErrorCode,
         IpcError,
         IpcResult,
         PacketError,
+        RpcError,
     },
     events::{
         Event,
         EventHandler,
+        EventPayload,
+        Subscription,
+    },
+    guild::{
+        Channel,
+        Guild,
+        PartialChannel,
+        PartialGuild,
+    },
+    lobby::{
+        Lobby,
+        LobbyOptions,
+        LobbySearch,
+    },
+    message::{
+        MessageDelete,
+        MessageEvent,
+        Notification,
+    },
+    overlay::{
+        ActivityActionType,
+        OverlayState,
+    },
+    store::{
+        Entitlement,
+        Sku,
+        UserAchievement,
+    },
+    user::{
+        Relationship,
+        User,
+    },
+    voice::{
+        Device,
+        SelectVoiceChannelOptions,
+        UserVoiceSettings,
+        VoiceSettings,
     },
     Activity,
     Header,
@@ -17,11 +74,14 @@ use crate::{
     Payload,
 };
 use std::{
+    collections::HashMap,
     io::{
         Read,
         Write,
     },
     str::from_utf8,
+    sync::mpsc,
+    time::Duration,
 };
 pub trait IpcClient {
     fn open(&mut self) -> IpcResult<()>;
@@ -29,26 +89,87 @@ pub trait IpcClient {
     fn write(&mut self, packet: Packet) -> IpcResult<()>;
 }
 
+/// The connection frames are read from and written to, the Discord pipe or socket by default.
+pub trait IpcSource: Read + Write + Send {}
+
+impl<T> IpcSource for T where T: Read + Write + Send {}
+
 pub struct DiscordIpcClient {
     app_id: &'static str,
     connected: bool,
     event_handler: EventHandler,
-    #[cfg(windows)]
-    source: Option<std::fs::File>,
-    #[cfg(unix)]
-    source: Option<std::os::unix::net::UnixStream>,
+    subscriptions: HashMap<(Event, String), usize>,
+    unsubscribe_tx: mpsc::Sender<(Event, Value)>,
+    unsubscribe_rx: mpsc::Receiver<(Event, Value)>,
+    session: Option<AuthenticateResponse>,
+    session_token: Option<StoredToken>,
+    session_error: Option<IpcError>,
+    overlay_state: Option<OverlayState>,
+    token_store: Option<Box<dyn TokenStore + Send>>,
+    token_refresh: Option<Box<dyn TokenRefresh + Send>>,
+    source: Option<Box<dyn IpcSource>>,
 }
 
 impl DiscordIpcClient {
     pub fn new(app_id: &'static str) -> Self {
+        let (unsubscribe_tx, unsubscribe_rx) = mpsc::channel();
         Self {
             app_id,
             connected: false,
             source: None,
             event_handler: EventHandler::new(),
+            subscriptions: HashMap::new(),
+            unsubscribe_tx,
+            unsubscribe_rx,
+            session: None,
+            session_token: None,
+            session_error: None,
+            overlay_state: None,
+            token_store: None,
+            token_refresh: None,
         }
     }
 
+    /// Uses `source` instead of opening the Discord pipe, e.g. a proxy or an in-memory connection.
+    pub fn with_source<S>(app_id: &'static str, source: S) -> Self
+    where
+        S: IpcSource + 'static,
+    {
+        let mut client = Self::new(app_id);
+        client.source = Some(Box::new(source));
+        client
+    }
+
+    /// Stores the token obtained by [`DiscordIpcClient::login`], and authenticates with it on
+    /// every following [`DiscordIpcClient::connect`].
+    pub fn set_token_store<S>(&mut self, store: S)
+    where
+        S: TokenStore + Send + 'static,
+    {
+        self.token_store = Some(Box::new(store));
+    }
+
+    /// Called when the stored token is about to expire or gets rejected by Discord, including
+    /// during long sessions, before the next command once the token expires within an hour.
+    pub fn set_token_refresh<R>(&mut self, refresh: R)
+    where
+        R: TokenRefresh + Send + 'static,
+    {
+        self.token_refresh = Some(Box::new(refresh));
+    }
+
+    /// The result of the last successful `AUTHENTICATE`.
+    pub fn session(&self) -> Option<&AuthenticateResponse> {
+        self.session.as_ref()
+    }
+
+    /// Why restoring the stored session on [`DiscordIpcClient::connect`], or refreshing it later,
+    /// failed. The client keeps working without a session, so apps can fall back to
+    /// [`DiscordIpcClient::login`].
+    pub fn take_session_error(&mut self) -> Option<IpcError> {
+        self.session_error.take()
+    }
+
     pub fn connect(&mut self) -> IpcResult<()> {
         if self.source.is_none() {
             Err(IpcError::ConnectionError(String::from(
@@ -66,6 +187,12 @@ impl DiscordIpcClient {
                 Err(_) => return Err(IpcError::HandshakeError(PacketError::SendError)),
             };
             self.write(packet)?;
+            while !self.connected {
+                self.receive()?;
+            }
+            if let Err(err) = self.restore_session() {
+                self.session_error = Some(err);
+            }
             Ok(())
         }
     }
@@ -75,88 +202,764 @@ impl DiscordIpcClient {
     }
 
     pub fn set_activity(&mut self, activity: Activity) -> IpcResult<()> {
-        self.write(Packet::new(
-            0x0001,
-            Payload::OutGoingCommand {
-                cmd: "SET_ACTIVITY".into(),
-                nonce: uuid::Uuid::new_v4().as_u128() as i64,
-                args: json!({
-                    "pid": std::process::id(),
-                    "activity": serde_json::to_value(activity)?
-                }),
-                evt: None,
-            },
-        )?)?;
+        activity.validate().map_err(IpcError::InvalidActivity)?;
+        self.set_activity_value(serde_json::to_value(activity)?)
+    }
+
+    /// Sends an already validated and serialized activity.
+    pub(crate) fn set_activity_value(&mut self, activity: Value) -> IpcResult<()> {
+        self.send_command(
+            Command::SetActivity,
+            json!({
+                "pid": std::process::id(),
+                "activity": activity
+            }),
+            None,
+        )?;
         Ok(())
     }
 
     pub fn clear_activity(&mut self) -> IpcResult<()> {
-        self.write(Packet::new(
-            0x0001,
-            Payload::OutGoingCommand {
-                cmd: "SET_ACTIVITY".into(),
-                nonce: uuid::Uuid::new_v4().as_u128() as i64,
-                args: json!({
-                    "pid": std::process::id()
-                }),
-                evt: None,
-            },
-        )?)?;
+        self.send_command(
+            Command::SetActivity,
+            json!({
+                "pid": std::process::id()
+            }),
+            None,
+        )?;
         Ok(())
     }
 
-    pub fn on<Callback>(&self, ev: Event, mut cb: Callback) -> IpcResult<()>
+    /// Accepts an Ask to Join request from `user_id`, see [`crate::JoinRequest`].
+    pub fn accept_join_request(&mut self, user_id: &str) -> IpcResult<()> {
+        self.send_command(
+            Command::SendActivityJoinInvite,
+            json!({ "user_id": user_id }),
+            None,
+        )?;
+        Ok(())
+    }
+
+    pub fn reject_join_request(&mut self, user_id: &str) -> IpcResult<()> {
+        self.send_command(
+            Command::CloseActivityRequest,
+            json!({ "user_id": user_id }),
+            None,
+        )?;
+        Ok(())
+    }
+
+    /// Subscribes to `ev` without arguments, see [`DiscordIpcClient::subscribe`].
+    #[must_use = "the callback is removed when the Subscription is dropped"]
+    pub fn on<Callback>(&mut self, ev: Event, cb: Callback) -> IpcResult<Subscription>
     where
         Callback: FnMut(Payload) + Send + 'static,
     {
-        if self.connected {
-            self.event_handler.listen(
-                move |event| {
-                    cb(event);
-                },
-                ev,
-            );
-            Ok(())
-        } else {
-            Err(IpcError::EventError)
+        self.subscribe(ev, json!({}), cb)
+    }
+
+    /// Registers `cb` for `ev` and sends `SUBSCRIBE` with `args` (for example
+    /// `{"channel_id": ...}` for voice events) so that Discord starts dispatching it.
+    ///
+    /// The subscription lasts until the returned [`Subscription`] is dropped.
+    #[must_use = "the callback is removed when the Subscription is dropped"]
+    pub fn subscribe<Callback>(
+        &mut self,
+        ev: Event,
+        args: Value,
+        cb: Callback,
+    ) -> IpcResult<Subscription>
+    where
+        Callback: FnMut(Payload) + Send + 'static,
+    {
+        if !self.connected {
+            return Err(IpcError::EventError);
+        }
+
+        if ev.requires_subscription() {
+            let key = (ev.clone(), args.to_string());
+            if !self.subscriptions.contains_key(&key) {
+                self.send_command(Command::Subscribe, args.clone(), Some(ev.clone()))?;
+            }
+            *self.subscriptions.entry(key).or_default() += 1;
         }
+
+        let listener_id = self.event_handler.listen(cb, ev.clone(), args.clone());
+        Ok(Subscription::new(
+            Some(ev),
+            args,
+            listener_id,
+            &self.event_handler,
+            self.unsubscribe_tx.clone(),
+        ))
+    }
+
+    /// Registers `cb` for every event dispatched by Discord, including the ones the crate doesn't
+    /// know yet (see [`Event::Unknown`]). It doesn't subscribe to anything by itself.
+    #[must_use = "the callback is removed when the Subscription is dropped"]
+    pub fn on_any<Callback>(&mut self, cb: Callback) -> IpcResult<Subscription>
+    where
+        Callback: FnMut(Payload) + Send + 'static,
+    {
+        if !self.connected {
+            return Err(IpcError::EventError);
+        }
+
+        let listener_id = self.event_handler.listen_all(cb);
+        Ok(Subscription::new(
+            None,
+            json!({}),
+            listener_id,
+            &self.event_handler,
+            self.unsubscribe_tx.clone(),
+        ))
+    }
+
+    /// Like [`DiscordIpcClient::on`], but hands `cb` the event's typed [`EventPayload`].
+    #[must_use = "the callback is removed when the Subscription is dropped"]
+    pub fn on_event<Callback>(&mut self, ev: Event, cb: Callback) -> IpcResult<Subscription>
+    where
+        Callback: FnMut(EventPayload) + Send + 'static,
+    {
+        self.subscribe_event(ev, json!({}), cb)
+    }
+
+    /// Like [`DiscordIpcClient::subscribe`], but hands `cb` the event's typed [`EventPayload`].
+    #[must_use = "the callback is removed when the Subscription is dropped"]
+    pub fn subscribe_event<Callback>(
+        &mut self,
+        ev: Event,
+        args: Value,
+        mut cb: Callback,
+    ) -> IpcResult<Subscription>
+    where
+        Callback: FnMut(EventPayload) + Send + 'static,
+    {
+        self.subscribe(ev, args, move |payload: Payload| {
+            if let Some(event) = EventPayload::from_payload(&payload) {
+                cb(event);
+            }
+        })
+    }
+
+    /// Calls `cb` with every message sent in `channel_id`.
+    #[must_use = "the callback is removed when the Subscription is dropped"]
+    pub fn on_message_create<Callback>(
+        &mut self,
+        channel_id: &str,
+        cb: Callback,
+    ) -> IpcResult<Subscription>
+    where
+        Callback: FnMut(MessageEvent) + Send + 'static,
+    {
+        self.subscribe_typed(
+            Event::MessageCreate,
+            json!({ "channel_id": channel_id }),
+            cb,
+        )
+    }
+
+    #[must_use = "the callback is removed when the Subscription is dropped"]
+    pub fn on_message_update<Callback>(
+        &mut self,
+        channel_id: &str,
+        cb: Callback,
+    ) -> IpcResult<Subscription>
+    where
+        Callback: FnMut(MessageEvent) + Send + 'static,
+    {
+        self.subscribe_typed(
+            Event::MessageUpdate,
+            json!({ "channel_id": channel_id }),
+            cb,
+        )
+    }
+
+    #[must_use = "the callback is removed when the Subscription is dropped"]
+    pub fn on_message_delete<Callback>(
+        &mut self,
+        channel_id: &str,
+        cb: Callback,
+    ) -> IpcResult<Subscription>
+    where
+        Callback: FnMut(MessageDelete) + Send + 'static,
+    {
+        self.subscribe_typed(
+            Event::MessageDelete,
+            json!({ "channel_id": channel_id }),
+            cb,
+        )
+    }
+
+    #[must_use = "the callback is removed when the Subscription is dropped"]
+    pub fn on_notification<Callback>(&mut self, cb: Callback) -> IpcResult<Subscription>
+    where
+        Callback: FnMut(Notification) + Send + 'static,
+    {
+        self.subscribe_typed(Event::NotificationCreate, json!({}), cb)
+    }
+
+    /// Like [`DiscordIpcClient::subscribe`], but hands `cb` the event's data deserialized into
+    /// `T`. Payloads that don't match `T` are skipped.
+    fn subscribe_typed<T, Callback>(
+        &mut self,
+        ev: Event,
+        args: Value,
+        mut cb: Callback,
+    ) -> IpcResult<Subscription>
+    where
+        T: DeserializeOwned,
+        Callback: FnMut(T) + Send + 'static,
+    {
+        self.subscribe(ev, args, move |payload: Payload| {
+            if let Ok(data) = payload.data() {
+                cb(data);
+            }
+        })
+    }
+
+    /// Blocks until Discord sends a frame and dispatches it to the listeners. Events are otherwise
+    /// only read while waiting for the response to a command, so apps that mostly listen should
+    /// call this in a loop.
+    ///
+    /// `UNSUBSCRIBE`s queued by dropped subscriptions are sent first.
+    pub fn poll(&mut self) -> IpcResult<()> {
+        self.refresh_expiring_session();
+        self.flush_unsubscriptions()?;
+        self.receive()?;
+        Ok(())
     }
 
     pub fn disconnect(&mut self) -> IpcResult<()> {
         self.write(Packet::new(0x0002, Payload::Empty {})?)?;
         self.connected = false;
+        self.subscriptions.clear();
         self.event_handler.stop();
         Ok(())
     }
-}
 
-#[cfg(windows)]
-impl IpcClient for DiscordIpcClient {
-    fn open(&mut self) -> IpcResult<()> {
-        if self.source.is_some() {
-            Ok(())
-        } else {
-            for i in 0..9 {
-                let ipc_path = format!("{}{}{}", crate::consts::IPC_DIR, IPC_PREFIX, i);
-                if let Ok(file) = std::fs::OpenOptions::new()
-                    .write(true)
-                    .read(true)
-                    .open(&ipc_path)
-                {
-                    return {
-                        self.source = Some(file);
-                        Ok(())
-                    };
-                } else {
+    /// Asks the user to authorize the application, returning the OAuth2 code to exchange for a
+    /// token.
+    pub fn authorize(&mut self, options: AuthorizeOptions) -> IpcResult<AuthorizeResponse> {
+        let mut args = serde_json::to_value(options)?;
+        args["client_id"] = json!(self.app_id);
+        let data = self.send_command(Command::Authorize, args, None)?;
+        Ok(serde_json::from_value(data)?)
+    }
+
+    pub fn authenticate(&mut self, access_token: &str) -> IpcResult<AuthenticateResponse> {
+        let data = self.send_command(
+            Command::Authenticate,
+            json!({ "access_token": access_token }),
+            None,
+        )?;
+        let session: AuthenticateResponse = serde_json::from_value(data)?;
+        self.session = Some(session.clone());
+        Ok(session)
+    }
+
+    /// Runs the whole flow: `AUTHORIZE`, the code to token `exchange`, then `AUTHENTICATE`.
+    pub fn login<E>(
+        &mut self,
+        options: AuthorizeOptions,
+        mut exchange: E,
+    ) -> IpcResult<(OAuthToken, AuthenticateResponse)>
+    where
+        E: TokenExchange,
+    {
+        let code = self.authorize(options)?.code;
+        let token = exchange
+            .exchange(&code)
+            .map_err(IpcError::TokenExchangeError)?;
+        let authenticated = self.authenticate(&token.access_token)?;
+        let stored = StoredToken::new(token.clone());
+        if let Some(store) = self.token_store.as_mut() {
+            store.save(&stored)?;
+        }
+        self.session_token = Some(stored);
+        Ok((token, authenticated))
+    }
+
+    pub fn get_guilds(&mut self) -> IpcResult<Vec<PartialGuild>> {
+        let mut data = self.send_command(Command::GetGuilds, json!({}), None)?;
+        Ok(serde_json::from_value(data["guilds"].take())?)
+    }
+
+    /// `timeout` is how long Discord may wait for the guild to become available, in seconds.
+    pub fn get_guild(&mut self, guild_id: &str, timeout: Option<u32>) -> IpcResult<Guild> {
+        let mut args = json!({ "guild_id": guild_id });
+        if let Some(timeout) = timeout {
+            args["timeout"] = json!(timeout);
+        }
+        let data = self.send_command(Command::GetGuild, args, None)?;
+        Ok(serde_json::from_value(data)?)
+    }
+
+    pub fn get_channels(&mut self, guild_id: &str) -> IpcResult<Vec<PartialChannel>> {
+        let mut data =
+            self.send_command(Command::GetChannels, json!({ "guild_id": guild_id }), None)?;
+        Ok(serde_json::from_value(data["channels"].take())?)
+    }
+
+    pub fn get_channel(&mut self, channel_id: &str) -> IpcResult<Channel> {
+        let data = self.send_command(
+            Command::GetChannel,
+            json!({ "channel_id": channel_id }),
+            None,
+        )?;
+        Ok(serde_json::from_value(data)?)
+    }
+
+    /// Joins the voice channel `channel_id`, returning it once joined.
+    pub fn select_voice_channel(
+        &mut self,
+        channel_id: &str,
+        options: SelectVoiceChannelOptions,
+    ) -> IpcResult<Option<Channel>> {
+        let mut args = serde_json::to_value(options)?;
+        args["channel_id"] = json!(channel_id);
+        let data = self.send_command(Command::SelectVoiceChannel, args, None)?;
+        Ok(serde_json::from_value(data)?)
+    }
+
+    pub fn leave_voice_channel(&mut self) -> IpcResult<()> {
+        self.send_command(
+            Command::SelectVoiceChannel,
+            json!({ "channel_id": null }),
+            None,
+        )?;
+        Ok(())
+    }
+
+    /// Returns `None` if the user isn't in a voice channel.
+    pub fn get_selected_voice_channel(&mut self) -> IpcResult<Option<Channel>> {
+        let data = self.send_command(Command::GetSelectedVoiceChannel, json!({}), None)?;
+        Ok(serde_json::from_value(data)?)
+    }
+
+    /// Opens the text channel `channel_id`, or closes the current one if `None`.
+    pub fn select_text_channel(
+        &mut self,
+        channel_id: Option<&str>,
+        timeout: Option<u32>,
+    ) -> IpcResult<Option<Channel>> {
+        let mut args = json!({ "channel_id": channel_id });
+        if let Some(timeout) = timeout {
+            args["timeout"] = json!(timeout);
+        }
+        let data = self.send_command(Command::SelectTextChannel, args, None)?;
+        Ok(serde_json::from_value(data)?)
+    }
+
+    pub fn get_voice_settings(&mut self) -> IpcResult<VoiceSettings> {
+        let data = self.send_command(Command::GetVoiceSettings, json!({}), None)?;
+        Ok(serde_json::from_value(data)?)
+    }
+
+    /// Only the fields set in `settings` are changed. Returns the resulting settings.
+    pub fn set_voice_settings(&mut self, settings: &VoiceSettings) -> IpcResult<VoiceSettings> {
+        let data = self.send_command(
+            Command::SetVoiceSettings,
+            serde_json::to_value(settings)?,
+            None,
+        )?;
+        Ok(serde_json::from_value(data)?)
+    }
+
+    /// Sets the local volume, pan or mute of another user in voice, returning the updated
+    /// settings.
+    pub fn set_user_voice_settings(
+        &mut self,
+        settings: &UserVoiceSettings,
+    ) -> IpcResult<UserVoiceSettings> {
+        settings.validate()?;
+        let data = self.send_command(
+            Command::SetUserVoiceSettings,
+            serde_json::to_value(settings)?,
+            None,
+        )?;
+        Ok(serde_json::from_value(data)?)
+    }
+
+    /// Reports the hardware certified devices plugged in, replacing the previously reported ones.
+    pub fn set_certified_devices(&mut self, devices: &[Device]) -> IpcResult<()> {
+        self.send_command(
+            Command::SetCertifiedDevices,
+            json!({ "devices": devices }),
+            None,
+        )?;
+        Ok(())
+    }
+
+    /// Starts recording a key combination through Discord. The keys pressed are reported with
+    /// `CAPTURE_SHORTCUT_CHANGE` events, see [`crate::ShortcutChange`].
+    pub fn capture_shortcut_start(&mut self) -> IpcResult<()> {
+        self.send_command(Command::CaptureShortcut, json!({ "action": "START" }), None)?;
+        Ok(())
+    }
+
+    pub fn capture_shortcut_stop(&mut self) -> IpcResult<()> {
+        self.send_command(Command::CaptureShortcut, json!({ "action": "STOP" }), None)?;
+        Ok(())
+    }
+
+    pub fn create_lobby(&mut self, options: &LobbyOptions) -> IpcResult<Lobby> {
+        let data = self.send_command(Command::CreateLobby, serde_json::to_value(options)?, None)?;
+        Ok(serde_json::from_value(data)?)
+    }
+
+    pub fn update_lobby(&mut self, lobby_id: &str, options: &LobbyOptions) -> IpcResult<()> {
+        let mut args = serde_json::to_value(options)?;
+        args["id"] = json!(lobby_id);
+        self.send_command(Command::UpdateLobby, args, None)?;
+        Ok(())
+    }
+
+    pub fn delete_lobby(&mut self, lobby_id: &str) -> IpcResult<()> {
+        self.send_command(Command::DeleteLobby, json!({ "id": lobby_id }), None)?;
+        Ok(())
+    }
+
+    pub fn connect_to_lobby(&mut self, lobby_id: &str, secret: &str) -> IpcResult<Lobby> {
+        let data = self.send_command(
+            Command::ConnectToLobby,
+            json!({ "id": lobby_id, "secret": secret }),
+            None,
+        )?;
+        Ok(serde_json::from_value(data)?)
+    }
+
+    pub fn disconnect_from_lobby(&mut self, lobby_id: &str) -> IpcResult<()> {
+        self.send_command(
+            Command::DisconnectFromLobby,
+            json!({ "id": lobby_id }),
+            None,
+        )?;
+        Ok(())
+    }
+
+    /// Sends `data` to every member of the lobby, received as `LOBBY_MESSAGE` events.
+    pub fn send_to_lobby(&mut self, lobby_id: &str, data: &str) -> IpcResult<()> {
+        self.send_command(
+            Command::SendToLobby,
+            json!({ "lobby_id": lobby_id, "data": data }),
+            None,
+        )?;
+        Ok(())
+    }
+
+    pub fn search_lobbies(&mut self, search: &LobbySearch) -> IpcResult<Vec<Lobby>> {
+        let data =
+            self.send_command(Command::SearchLobbies, serde_json::to_value(search)?, None)?;
+        Ok(serde_json::from_value(data)?)
+    }
+
+    pub fn get_relationships(&mut self) -> IpcResult<Vec<Relationship>> {
+        let mut data = self.send_command(Command::GetRelationships, json!({}), None)?;
+        Ok(serde_json::from_value(data["relationships"].take())?)
+    }
+
+    pub fn get_user(&mut self, user_id: &str) -> IpcResult<User> {
+        let data = self.send_command(Command::GetUser, json!({ "id": user_id }), None)?;
+        Ok(serde_json::from_value(data)?)
+    }
+
+    /// The overlay state cached from the last `OVERLAY` or `OVERLAY_UPDATE` event. Discord only
+    /// sends those to subscribers, so this is `None` until something subscribes to
+    /// [`Event::OverlayUpdate`], and is only kept up to date while the subscription lives.
+    pub fn last_overlay_state(&self) -> Option<OverlayState> {
+        self.overlay_state
+    }
+
+    /// Locking the overlay closes it, along with any invite it shows, and keeps the user from
+    /// opening it until it is unlocked again.
+    pub fn set_overlay_locked(&mut self, locked: bool) -> IpcResult<()> {
+        self.send_command(
+            Command::SetOverlayLocked,
+            json!({ "locked": locked, "pid": std::process::id() }),
+            None,
+        )?;
+        Ok(())
+    }
+
+    /// Opens the overlay to invite friends to join or spectate the current activity.
+    pub fn open_activity_invite(&mut self, kind: ActivityActionType) -> IpcResult<()> {
+        self.send_command(
+            Command::OpenOverlayActivityInvite,
+            json!({ "type": kind, "pid": std::process::id() }),
+            None,
+        )?;
+        Ok(())
+    }
+
+    /// Opens the overlay to accept the guild invite `code`.
+    pub fn open_guild_invite(&mut self, code: &str) -> IpcResult<()> {
+        self.send_command(
+            Command::OpenOverlayGuildInvite,
+            json!({ "code": code, "pid": std::process::id() }),
+            None,
+        )?;
+        Ok(())
+    }
+
+    pub fn open_voice_settings(&mut self) -> IpcResult<()> {
+        self.send_command(
+            Command::OpenOverlayVoiceSettings,
+            json!({ "pid": std::process::id() }),
+            None,
+        )?;
+        Ok(())
+    }
+
+    pub fn get_entitlements(&mut self) -> IpcResult<Vec<Entitlement>> {
+        let data = self.send_command(Command::GetEntitlements, json!({}), None)?;
+        Ok(serde_json::from_value(data)?)
+    }
+
+    pub fn get_skus(&mut self) -> IpcResult<Vec<Sku>> {
+        let data = self.send_command(Command::GetSkus, json!({}), None)?;
+        Ok(serde_json::from_value(data)?)
+    }
+
+    /// Sets the current user's progress on `achievement_id`, 100 unlocks it.
+    pub fn set_user_achievement(
+        &mut self,
+        achievement_id: &str,
+        percent_complete: u8,
+    ) -> IpcResult<()> {
+        if percent_complete > 100 {
+            return Err(IpcError::InvalidArgument(format!(
+                "percent_complete must be between 0 and 100, got {}",
+                percent_complete
+            )));
+        }
+        self.send_command(
+            Command::SetUserAchievement,
+            json!({
+                "achievement_id": achievement_id,
+                "percent_complete": percent_complete
+            }),
+            None,
+        )?;
+        Ok(())
+    }
+
+    pub fn get_user_achievements(&mut self) -> IpcResult<Vec<UserAchievement>> {
+        let data = self.send_command(Command::GetUserAchievements, json!({}), None)?;
+        Ok(serde_json::from_value(data)?)
+    }
+
+    /// Authenticates with the stored token, refreshing it first if it is about to expire. A
+    /// rejected token is refreshed once, or dropped from the store if there is no way to refresh
+    /// it, in which case the app has to [`DiscordIpcClient::login`] again.
+    fn restore_session(&mut self) -> IpcResult<()> {
+        let Some(store) = self.token_store.as_mut() else {
+            return Ok(());
+        };
+        let Some(mut stored) = store.load()? else {
+            return Ok(());
+        };
+
+        if stored.expires_within(TOKEN_REFRESH_MARGIN) {
+            match self.refresh_stored_token(&stored) {
+                Ok(Some(refreshed)) => stored = refreshed,
+                Ok(None) => (),
+                // The current token is still good until it expires.
+                Err(err) if !stored.expires_within(Duration::ZERO) => {
+                    self.session_error = Some(err);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        match self.authenticate(&stored.token.access_token) {
+            Ok(_) => {
+                self.session_token = Some(stored);
+                return Ok(());
+            }
+            Err(IpcError::Rpc(RpcError {
+                code: ErrorCode::InvalidToken | ErrorCode::InvalidPermissions,
+                ..
+            })) => (),
+            Err(err) => return Err(err),
+        }
+        match self.refresh_stored_token(&stored)? {
+            Some(refreshed) => {
+                self.authenticate(&refreshed.token.access_token)?;
+                self.session_token = Some(refreshed);
+            }
+            None => {
+                if let Some(store) = self.token_store.as_mut() {
+                    store.clear()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Refreshes the session token before it expires. A failure is kept for
+    /// [`DiscordIpcClient::take_session_error`] and not retried, the current token stays valid
+    /// until it expires.
+    fn refresh_expiring_session(&mut self) {
+        if !self
+            .session_token
+            .as_ref()
+            .is_some_and(|stored| stored.expires_within(TOKEN_REFRESH_MARGIN))
+        {
+            return;
+        }
+        if let Err(err) = self.refresh_session() {
+            self.session_error = Some(err);
+        }
+    }
+
+    /// Refreshes the session token and authenticates with the new one. The session is dropped if
+    /// that fails or if there is no way to refresh it.
+    fn refresh_session(&mut self) -> IpcResult<()> {
+        let Some(stored) = self.session_token.take() else {
+            return Ok(());
+        };
+        if let Some(refreshed) = self.refresh_stored_token(&stored)? {
+            self.authenticate(&refreshed.token.access_token)?;
+            self.session_token = Some(refreshed);
+        }
+        Ok(())
+    }
+
+    fn refresh_stored_token(&mut self, stored: &StoredToken) -> IpcResult<Option<StoredToken>> {
+        let Some(refresh) = self.token_refresh.as_mut() else {
+            return Ok(None);
+        };
+        let refreshed = StoredToken::new(
+            refresh
+                .refresh(&stored.token)
+                .map_err(IpcError::TokenRefreshError)?,
+        );
+        if let Some(store) = self.token_store.as_mut() {
+            store.save(&refreshed)?;
+        }
+        Ok(Some(refreshed))
+    }
+
+    /// Sends `cmd` and waits for the response carrying the same nonce, returning its `data`.
+    /// Events received in the meantime are handed to the event handler.
+    ///
+    /// A command rejected because the session token was revoked is sent again once the token has
+    /// been refreshed.
+    pub(crate) fn send_command(
+        &mut self,
+        cmd: Command,
+        args: Value,
+        evt: Option<Event>,
+    ) -> IpcResult<Value> {
+        if cmd == Command::Authenticate {
+            self.flush_unsubscriptions()?;
+            return self.request(cmd, args, evt);
+        }
+
+        self.refresh_expiring_session();
+        self.flush_unsubscriptions()?;
+        match self.request(cmd.clone(), args.clone(), evt.clone()) {
+            Err(
+                err @ IpcError::Rpc(RpcError {
+                    code: ErrorCode::InvalidToken | ErrorCode::InvalidPermissions,
+                    ..
+                }),
+            ) if self.session_token.is_some() => {
+                self.refresh_session()?;
+                if self.session_token.is_none() {
+                    return Err(err);
+                }
+                self.request(cmd, args, evt)
+            }
+            result => result,
+        }
+    }
+
+    /// Sends an `UNSUBSCRIBE` for every event whose last subscription has been dropped.
+    fn flush_unsubscriptions(&mut self) -> IpcResult<()> {
+        while let Ok((ev, args)) = self.unsubscribe_rx.try_recv() {
+            let key = (ev, args.to_string());
+            if let Some(count) = self.subscriptions.get_mut(&key) {
+                *count -= 1;
+                if *count == 0 {
+                    self.subscriptions.remove(&key);
+                    self.request(Command::Unsubscribe, args, Some(key.0))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn request(&mut self, cmd: Command, args: Value, evt: Option<Event>) -> IpcResult<Value> {
+        let nonce = uuid::Uuid::new_v4().to_string();
+        self.write(Packet::new(
+            0x0001,
+            Payload::OutGoingCommand {
+                cmd,
+                nonce: nonce.clone(),
+                args,
+                evt,
+            },
+        )?)?;
+
+        loop {
+            if let Payload::InComingCommand {
+                nonce: response_nonce,
+                data,
+                evt,
+                ..
+            } = self.receive()?
+            {
+                if response_nonce != nonce {
                     continue;
                 }
+                return match evt {
+                    Some(Event::Error) => Err(IpcError::Rpc(RpcError::from_data(&data))),
+                    _ => Ok(data),
+                };
             }
-            Err(IpcError::OpenError(String::from(
-                "Couldn't find an available discord ipc path",
-            )))
         }
     }
 
+    /// Reads a single frame, answering pings and dispatching events.
+    fn receive(&mut self) -> IpcResult<Payload> {
+        let res = self.read()?;
+        match res.payload {
+            Payload::InComingCommand {
+                cmd: Command::Dispatch,
+                evt: Some(ref event),
+                ..
+            } => {
+                match event {
+                    Event::Ready => self.connected = true,
+                    Event::Overlay | Event::OverlayUpdate => {
+                        self.overlay_state = res.payload.data().ok();
+                    }
+                    _ => (),
+                }
+                self.event_handler.emit(event.clone(), res.payload.clone());
+            }
+            Payload::CriticalError { code: _, message } => {
+                return Err(IpcError::CriticalError(message));
+            }
+            _ if res.header.opcode == 0x0003 => {
+                self.write(Packet::new(0x0004, res.payload.clone())?)?;
+            }
+            _ => (),
+        }
+        Ok(res.payload)
+    }
+}
+
+impl IpcClient for DiscordIpcClient {
+    fn open(&mut self) -> IpcResult<()> {
+        if self.source.is_none() {
+            self.source = Some(open_source()?);
+        }
+        Ok(())
+    }
+
     fn read(&mut self) -> IpcResult<Packet> {
         if let Some(ref mut source) = &mut self.source {
             let mut header = vec![0u8; 8];
@@ -182,37 +985,923 @@ impl IpcClient for DiscordIpcClient {
             source.write_all(&header)?;
             source.write_all(&data)?;
 
-            let res = self.read()?;
-            match res.payload {
+            Ok(())
+        } else {
+            Err(IpcError::WriteError(String::from(
+                "There is no valid source provided, please open a source first",
+            )))
+        }
+    }
+}
+
+#[cfg(windows)]
+fn open_source() -> IpcResult<Box<dyn IpcSource>> {
+    for i in 0..9 {
+        let ipc_path = format!("{}{}{}", crate::consts::IPC_DIR, IPC_PREFIX, i);
+        if let Ok(file) = std::fs::OpenOptions::new()
+            .write(true)
+            .read(true)
+            .open(&ipc_path)
+        {
+            return Ok(Box::new(file));
+        }
+    }
+    Err(IpcError::OpenError(String::from(
+        "Couldn't find an available discord ipc path",
+    )))
+}
+
+#[cfg(unix)]
+fn open_source() -> IpcResult<Box<dyn IpcSource>> {
+    let dirs = crate::consts::IPC_DIRS
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .chain([String::from("/tmp")]);
+    for dir in dirs {
+        for i in 0..9 {
+            let ipc_path = std::path::Path::new(&dir).join(format!("{}{}", IPC_PREFIX, i));
+            if let Ok(stream) = std::os::unix::net::UnixStream::connect(&ipc_path) {
+                return Ok(Box::new(stream));
+            }
+        }
+    }
+    Err(IpcError::OpenError(String::from(
+        "Couldn't find an available discord ipc path",
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        io,
+        sync::{
+            Arc,
+            Mutex,
+        },
+        time::{
+            Duration,
+            Instant,
+        },
+    };
+
+    use super::*;
+    use crate::{
+        auth::Prompt,
+        PresenceManager,
+        VoiceChange,
+        VoiceChannelWatcher,
+    };
+
+    type Responder = Box<dyn FnMut(&Value) -> Vec<Value> + Send>;
+
+    /// An in-memory Discord that answers the handshake with `READY` and every command with the
+    /// frames returned by `respond`.
+    struct FakeDiscord {
+        written: Vec<u8>,
+        unread: VecDeque<u8>,
+        respond: Responder,
+    }
+
+    impl FakeDiscord {
+        fn new<F>(respond: F) -> Self
+        where
+            F: FnMut(&Value) -> Vec<Value> + Send + 'static,
+        {
+            Self {
+                written: Vec::new(),
+                unread: VecDeque::new(),
+                respond: Box::new(respond),
+            }
+        }
+
+        fn push_frame(&mut self, opcode: u32, payload: &Value) {
+            let data = payload.to_string();
+            self.unread.extend(opcode.to_le_bytes());
+            self.unread.extend((data.len() as u32).to_le_bytes());
+            self.unread.extend(data.bytes());
+        }
+    }
+
+    impl Read for FakeDiscord {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.unread.read(buf)
+        }
+    }
+
+    impl Write for FakeDiscord {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.extend_from_slice(buf);
+            while self.written.len() >= 8 {
+                let opcode = u32::from_le_bytes(self.written[..4].try_into().unwrap());
+                let length = u32::from_le_bytes(self.written[4..8].try_into().unwrap()) as usize;
+                if self.written.len() < 8 + length {
+                    break;
+                }
+                let frame: Vec<u8> = self.written.drain(..8 + length).skip(8).collect();
+                let request: Value = serde_json::from_slice(&frame).unwrap();
+                let responses = match opcode {
+                    0 => vec![dispatch("READY", json!({ "v": 1, "config": {} }))],
+                    _ => (self.respond)(&request),
+                };
+                for response in responses {
+                    self.push_frame(1, &response);
+                }
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn reply(request: &Value, data: Value) -> Value {
+        json!({
+            "cmd": request["cmd"],
+            "nonce": request["nonce"],
+            "evt": null,
+            "data": data,
+        })
+    }
+
+    fn dispatch(evt: &str, data: Value) -> Value {
+        json!({
+            "cmd": "DISPATCH",
+            "nonce": null,
+            "evt": evt,
+            "data": data,
+        })
+    }
+
+    #[derive(Clone, Default)]
+    struct MemoryStore(Arc<Mutex<Option<StoredToken>>>);
+
+    impl TokenStore for MemoryStore {
+        fn load(&mut self) -> io::Result<Option<StoredToken>> {
+            Ok(self.0.lock().unwrap().clone())
+        }
+
+        fn save(&mut self, token: &StoredToken) -> io::Result<()> {
+            *self.0.lock().unwrap() = Some(token.clone());
+            Ok(())
+        }
+
+        fn clear(&mut self) -> io::Result<()> {
+            *self.0.lock().unwrap() = None;
+            Ok(())
+        }
+    }
+
+    fn oauth_token(access_token: &str, expires_in: u64) -> OAuthToken {
+        OAuthToken {
+            access_token: access_token.to_owned(),
+            token_type: None,
+            expires_in: Some(expires_in),
+            refresh_token: Some("refresh".to_owned()),
+            scope: None,
+        }
+    }
+
+    fn authenticated(request: &Value) -> Vec<Value> {
+        vec![reply(
+            request,
+            json!({
+                "application": { "id": "1234", "name": "Test" },
+                "expires": "2030-01-01T00:00:00.000Z",
+                "user": { "id": "42", "username": "tester" },
+                "scopes": ["rpc"],
+            }),
+        )]
+    }
+
+    fn invalid_token(request: &Value) -> Vec<Value> {
+        vec![json!({
+            "cmd": request["cmd"],
+            "nonce": request["nonce"],
+            "evt": "ERROR",
+            "data": { "code": 4009, "message": "Invalid token" },
+        })]
+    }
+
+    fn connected_client(discord: FakeDiscord) -> DiscordIpcClient {
+        let mut client = DiscordIpcClient::with_source("1234", discord);
+        client.connect().unwrap();
+        client
+    }
+
+    #[test]
+    fn login_authorizes_exchanges_and_authenticates() {
+        let authenticated_with = Arc::new(Mutex::new(None));
+        let seen = Arc::clone(&authenticated_with);
+        let discord = FakeDiscord::new(move |request| match request["cmd"].as_str() {
+            Some("AUTHORIZE") => {
+                assert_eq!(request["args"]["client_id"], "1234");
+                vec![reply(request, json!({ "code": "canned-code" }))]
+            }
+            Some("AUTHENTICATE") => {
+                *seen.lock().unwrap() = request["args"]["access_token"].as_str().map(String::from);
+                authenticated(request)
+            }
+            _ => panic!("unexpected command {request}"),
+        });
+        let mut client = connected_client(discord);
+
+        let (token, session) = client
+            .login(
+                AuthorizeOptions::new(["rpc"]).set_prompt(Prompt::None),
+                |code: &str| {
+                    assert_eq!(code, "canned-code");
+                    Ok(OAuthToken {
+                        access_token: "access".to_owned(),
+                        token_type: Some("Bearer".to_owned()),
+                        expires_in: Some(604800),
+                        refresh_token: Some("refresh".to_owned()),
+                        scope: Some("rpc".to_owned()),
+                    })
+                },
+            )
+            .unwrap();
+
+        assert_eq!(token.access_token, "access");
+        assert_eq!(session.user.id, "42");
+        assert_eq!(client.session(), Some(&session));
+        assert_eq!(
+            authenticated_with.lock().unwrap().as_deref(),
+            Some("access")
+        );
+    }
+
+    #[test]
+    fn login_reports_exchange_errors() {
+        let discord = FakeDiscord::new(|request| match request["cmd"].as_str() {
+            Some("AUTHORIZE") => vec![reply(request, json!({ "code": "canned-code" }))],
+            _ => panic!("unexpected command {request}"),
+        });
+        let mut client = connected_client(discord);
+
+        let result = client.login(AuthorizeOptions::new(["rpc"]), |_: &str| {
+            Err(String::from("bad code"))
+        });
+
+        assert!(matches!(result, Err(IpcError::TokenExchangeError(err)) if err == "bad code"));
+        assert!(client.session().is_none());
+    }
+
+    #[test]
+    fn connect_survives_failed_session_restore() {
+        let discord = FakeDiscord::new(|request| match request["cmd"].as_str() {
+            Some("AUTHENTICATE") => invalid_token(request),
+            _ => panic!("unexpected command {request}"),
+        });
+        let store = MemoryStore::default();
+        *store.0.lock().unwrap() = Some(StoredToken::new(oauth_token("stale", 604800)));
+        let mut client = DiscordIpcClient::with_source("1234", discord);
+        client.set_token_store(store.clone());
+        client.set_token_refresh(|_: &OAuthToken| Err(String::from("revoked")));
+
+        client.connect().unwrap();
+
+        assert!(client.session().is_none());
+        assert!(matches!(
+            client.take_session_error(),
+            Some(IpcError::TokenRefreshError(err)) if err == "revoked"
+        ));
+    }
+
+    #[test]
+    fn connect_keeps_an_expiring_token_when_its_refresh_fails() {
+        let discord = FakeDiscord::new(|request| match request["cmd"].as_str() {
+            Some("AUTHENTICATE") => {
+                assert_eq!(request["args"]["access_token"], "current");
+                authenticated(request)
+            }
+            _ => panic!("unexpected command {request}"),
+        });
+        let store = MemoryStore::default();
+        *store.0.lock().unwrap() = Some(StoredToken::new(oauth_token("current", 60)));
+        let mut client = DiscordIpcClient::with_source("1234", discord);
+        client.set_token_store(store.clone());
+        client.set_token_refresh(|_: &OAuthToken| Err(String::from("offline")));
+
+        client.connect().unwrap();
+
+        assert!(client.session().is_some());
+        assert!(matches!(
+            client.take_session_error(),
+            Some(IpcError::TokenRefreshError(err)) if err == "offline"
+        ));
+    }
+
+    #[test]
+    fn revoked_session_is_refreshed_and_the_command_retried() {
+        let authenticated_with = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&authenticated_with);
+        let mut revoked = true;
+        let discord = FakeDiscord::new(move |request| match request["cmd"].as_str() {
+            Some("AUTHENTICATE") => {
+                seen.lock()
+                    .unwrap()
+                    .push(request["args"]["access_token"].as_str().unwrap().to_owned());
+                authenticated(request)
+            }
+            Some("AUTHORIZE") => vec![reply(request, json!({ "code": "canned-code" }))],
+            Some("GET_GUILDS") if revoked => {
+                revoked = false;
+                invalid_token(request)
+            }
+            Some("GET_GUILDS") => vec![reply(request, json!({ "guilds": [] }))],
+            _ => panic!("unexpected command {request}"),
+        });
+        let mut client = connected_client(discord);
+        client.set_token_refresh(|_: &OAuthToken| Ok(oauth_token("new", 604800)));
+        client
+            .login(AuthorizeOptions::new(["rpc"]), |_: &str| {
+                Ok(oauth_token("old", 604800))
+            })
+            .unwrap();
+
+        client.get_guilds().unwrap();
+
+        assert_eq!(*authenticated_with.lock().unwrap(), ["old", "new"]);
+    }
+
+    #[test]
+    fn revoked_session_is_only_retried_once() {
+        let discord = FakeDiscord::new(move |request| match request["cmd"].as_str() {
+            Some("AUTHENTICATE") => authenticated(request),
+            Some("AUTHORIZE") => vec![reply(request, json!({ "code": "canned-code" }))],
+            Some("GET_GUILDS") => invalid_token(request),
+            _ => panic!("unexpected command {request}"),
+        });
+        let mut client = connected_client(discord);
+        let mut refreshes = 0;
+        client.set_token_refresh(move |_: &OAuthToken| {
+            refreshes += 1;
+            assert_eq!(refreshes, 1);
+            Ok(oauth_token("new", 604800))
+        });
+        client
+            .login(AuthorizeOptions::new(["rpc"]), |_: &str| {
+                Ok(oauth_token("old", 604800))
+            })
+            .unwrap();
+
+        assert!(matches!(
+            client.get_guilds(),
+            Err(IpcError::Rpc(RpcError {
+                code: ErrorCode::InvalidToken,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn expiring_session_is_refreshed_before_commands() {
+        let authenticated_with = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&authenticated_with);
+        let discord = FakeDiscord::new(move |request| match request["cmd"].as_str() {
+            Some("AUTHENTICATE") => {
+                seen.lock()
+                    .unwrap()
+                    .push(request["args"]["access_token"].as_str().unwrap().to_owned());
+                authenticated(request)
+            }
+            Some("AUTHORIZE") => vec![reply(request, json!({ "code": "canned-code" }))],
+            Some("GET_GUILDS") => vec![reply(request, json!({ "guilds": [] }))],
+            _ => panic!("unexpected command {request}"),
+        });
+        let store = MemoryStore::default();
+        let mut client = DiscordIpcClient::with_source("1234", discord);
+        client.set_token_store(store.clone());
+        let mut refreshes = 0;
+        client.set_token_refresh(move |_: &OAuthToken| {
+            refreshes += 1;
+            Ok(oauth_token(&format!("new-{refreshes}"), 604800))
+        });
+        client.connect().unwrap();
+        // Valid for now, but within the refresh margin.
+        client
+            .login(AuthorizeOptions::new(["rpc"]), |_: &str| {
+                Ok(oauth_token("old", 60))
+            })
+            .unwrap();
+
+        client.get_guilds().unwrap();
+        client.get_guilds().unwrap();
+
+        assert_eq!(*authenticated_with.lock().unwrap(), ["old", "new-1"]);
+        assert_eq!(
+            store.0.lock().unwrap().as_ref().unwrap().token.access_token,
+            "new-1"
+        );
+    }
+
+    #[test]
+    fn unknown_events_reach_catch_all_listeners() {
+        let unknown = dispatch("SOME_NEW_EVENT", json!({ "answer": 42 }));
+        let payload: Payload = serde_json::from_value(unknown.clone()).unwrap();
+        assert!(matches!(
+            payload,
+            Payload::InComingCommand { evt: Some(Event::Unknown(ref name)), .. }
+                if name == "SOME_NEW_EVENT"
+        ));
+
+        let discord = FakeDiscord::new(move |request| match request["cmd"].as_str() {
+            Some("GET_GUILDS") => vec![unknown.clone(), reply(request, json!({ "guilds": [] }))],
+            _ => panic!("unexpected command {request}"),
+        });
+        let mut client = connected_client(discord);
+        let (tx, rx) = mpsc::channel();
+        let _subscription = client
+            .on_any(move |payload| {
+                let _ = tx.send(payload);
+            })
+            .unwrap();
+
+        client.get_guilds().unwrap();
+        // The connection survives the unknown event.
+        client.get_guilds().unwrap();
+
+        let received = std::iter::from_fn(|| rx.recv_timeout(Duration::from_secs(5)).ok())
+            .find_map(|payload| match payload {
                 Payload::InComingCommand {
-                    cmd: _,
-                    nonce: _,
-                    args: _,
-                    data: _,
-                    evt: Some(ref event),
-                } => {
-                    let ev_clone = event.clone();
-                    self.event_handler.emit(ev_clone.clone(), res.payload);
-                    if ev_clone == Event::Ready {
-                        self.connected = true;
-                    }
+                    evt: Some(Event::Unknown(name)),
+                    data,
+                    ..
+                } => Some((name, data)),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(
+            received,
+            ("SOME_NEW_EVENT".to_owned(), json!({ "answer": 42 }))
+        );
+    }
+
+    #[test]
+    fn events_only_reach_subscriptions_with_matching_arguments() {
+        fn message(channel_id: &str, id: &str) -> Value {
+            dispatch(
+                "MESSAGE_CREATE",
+                json!({
+                    "channel_id": channel_id,
+                    "message": { "id": id, "author": { "id": "42", "username": "tester" } },
+                }),
+            )
+        }
+
+        let discord = FakeDiscord::new(|request| match request["cmd"].as_str() {
+            Some("SUBSCRIBE") => vec![reply(request, json!({ "evt": request["evt"] }))],
+            Some("GET_GUILDS") => vec![
+                message("A", "1"),
+                message("B", "2"),
+                message("A", "3"),
+                reply(request, json!({ "guilds": [] })),
+            ],
+            _ => panic!("unexpected command {request}"),
+        });
+        let mut client = connected_client(discord);
+        let (a_tx, a_rx) = mpsc::channel();
+        let _a = client
+            .on_message_create("A", move |event| {
+                let _ = a_tx.send(event.message.id);
+            })
+            .unwrap();
+        let (b_tx, b_rx) = mpsc::channel();
+        let _b = client
+            .on_message_create("B", move |event| {
+                let _ = b_tx.send(event.message.id);
+            })
+            .unwrap();
+
+        client.get_guilds().unwrap();
+
+        let received_a: Vec<_> =
+            std::iter::from_fn(|| a_rx.recv_timeout(Duration::from_secs(5)).ok())
+                .take(2)
+                .collect();
+        assert_eq!(received_a, ["1", "3"]);
+        // Every event before "3" has been dispatched by now.
+        assert_eq!(b_rx.try_iter().collect::<Vec<_>>(), ["2"]);
+    }
+
+    /// A Discord that records the (un)subscriptions it receives. `UNSUBSCRIBE` is followed by an
+    /// event so that a `poll` sending it has something to read afterwards.
+    fn subscription_recorder() -> (FakeDiscord, Arc<Mutex<Vec<Value>>>) {
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&recorded);
+        let discord = FakeDiscord::new(move |request| match request["cmd"].as_str() {
+            Some(cmd @ ("SUBSCRIBE" | "UNSUBSCRIBE")) => {
+                seen.lock().unwrap().push(json!({
+                    "cmd": cmd,
+                    "evt": request["evt"],
+                    "args": request["args"],
+                }));
+                let mut frames = vec![reply(request, json!({ "evt": request["evt"] }))];
+                if cmd == "UNSUBSCRIBE" {
+                    frames.push(dispatch("SOME_NEW_EVENT", json!({})));
                 }
-                Payload::CriticalError { code: _, message } => {
-                    return Err(IpcError::CriticalError(message));
+                frames
+            }
+            Some("GET_GUILDS") => vec![reply(request, json!({ "guilds": [] }))],
+            _ => panic!("unexpected command {request}"),
+        });
+        (discord, recorded)
+    }
+
+    fn subscription(cmd: &str, channel_id: &str) -> Value {
+        json!({
+            "cmd": cmd,
+            "evt": "MESSAGE_CREATE",
+            "args": { "channel_id": channel_id },
+        })
+    }
+
+    #[test]
+    fn subscribe_is_sent_once_per_event_and_arguments() {
+        let (discord, recorded) = subscription_recorder();
+        let mut client = connected_client(discord);
+
+        let _a = client.on_message_create("A", |_| ()).unwrap();
+        let _b = client.on_message_create("B", |_| ()).unwrap();
+        let _a_again = client.on_message_create("A", |_| ()).unwrap();
+
+        assert_eq!(
+            *recorded.lock().unwrap(),
+            [
+                subscription("SUBSCRIBE", "A"),
+                subscription("SUBSCRIBE", "B")
+            ]
+        );
+    }
+
+    #[test]
+    fn dropping_the_last_subscription_unsubscribes_on_the_next_command() {
+        let (discord, recorded) = subscription_recorder();
+        let mut client = connected_client(discord);
+        let a = client.on_message_create("A", |_| ()).unwrap();
+        let a_again = client.on_message_create("A", |_| ()).unwrap();
+
+        drop(a);
+        client.get_guilds().unwrap();
+        assert_eq!(recorded.lock().unwrap().len(), 1);
+
+        drop(a_again);
+        client.get_guilds().unwrap();
+        client.get_guilds().unwrap();
+        assert_eq!(
+            *recorded.lock().unwrap(),
+            [
+                subscription("SUBSCRIBE", "A"),
+                subscription("UNSUBSCRIBE", "A")
+            ]
+        );
+    }
+
+    #[test]
+    fn dropping_the_last_subscription_unsubscribes_on_poll() {
+        let (discord, recorded) = subscription_recorder();
+        let mut client = connected_client(discord);
+        let a = client.on_message_create("A", |_| ()).unwrap();
+
+        drop(a);
+        client.poll().unwrap();
+
+        assert_eq!(
+            *recorded.lock().unwrap(),
+            [
+                subscription("SUBSCRIBE", "A"),
+                subscription("UNSUBSCRIBE", "A")
+            ]
+        );
+    }
+
+    #[test]
+    fn detached_subscriptions_are_never_unsubscribed() {
+        let (discord, recorded) = subscription_recorder();
+        let mut client = connected_client(discord);
+
+        client.on_message_create("A", |_| ()).unwrap().detach();
+        client.get_guilds().unwrap();
+
+        assert_eq!(*recorded.lock().unwrap(), [subscription("SUBSCRIBE", "A")]);
+    }
+
+    fn voice_state(id: &str, self_mute: bool) -> Value {
+        json!({
+            "user": { "id": id, "username": id },
+            "voice_state": { "self_mute": self_mute },
+        })
+    }
+
+    fn voice_channel(id: &str, voice_states: &[Value]) -> Value {
+        json!({
+            "id": id,
+            "guild_id": "20",
+            "name": "General",
+            "type": 2,
+            "voice_states": voice_states,
+        })
+    }
+
+    /// Answers `GET_GUILDS` with a `FLUSHED` event, see [`flush_events`].
+    fn flushed(request: &Value) -> Vec<Value> {
+        vec![
+            dispatch("FLUSHED", json!({})),
+            reply(request, json!({ "guilds": [] })),
+        ]
+    }
+
+    /// Waits until the dispatcher is done with every event received so far, which it handles in
+    /// order.
+    fn flush_events(client: &mut DiscordIpcClient) {
+        let (tx, rx) = mpsc::channel();
+        let _flushed = client
+            .on_any(move |payload| {
+                if matches!(
+                    payload,
+                    Payload::InComingCommand { evt: Some(Event::Unknown(ref name)), .. }
+                        if name == "FLUSHED"
+                ) {
+                    let _ = tx.send(());
                 }
-                Payload::Empty {} => {
-                    if res.header.opcode == 0x0003 {
-                        self.write(Packet::new(0x0004, Payload::Empty {})?)?
-                    }
+            })
+            .unwrap();
+        client.get_guilds().unwrap();
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+
+    fn member_states(watcher: &VoiceChannelWatcher) -> Vec<(String, bool)> {
+        watcher
+            .snapshot()
+            .members
+            .into_iter()
+            .map(|member| (member.user.id, member.voice_state.self_mute))
+            .collect()
+    }
+
+    #[test]
+    fn voice_watcher_subscribes_before_fetching_members() {
+        let commands = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&commands);
+        let discord = FakeDiscord::new(move |request| {
+            let cmd = request["cmd"].as_str().unwrap().to_owned();
+            seen.lock().unwrap().push(cmd.clone());
+            let channel = voice_channel(
+                "10",
+                &[voice_state("alice", false), voice_state("bob", false)],
+            );
+            match cmd.as_str() {
+                "GET_SELECTED_VOICE_CHANNEL" => vec![reply(request, channel)],
+                "SUBSCRIBE" => vec![reply(request, json!({ "evt": request["evt"] }))],
+                // Bob mutes while the channel is being fetched, the response is already stale.
+                "GET_CHANNEL" => vec![
+                    dispatch("VOICE_STATE_UPDATE", voice_state("bob", true)),
+                    reply(request, channel),
+                ],
+                "GET_GUILDS" => flushed(request),
+                _ => panic!("unexpected command {request}"),
+            }
+        });
+        let mut client = connected_client(discord);
+
+        let watcher = VoiceChannelWatcher::new(&mut client).unwrap();
+
+        let commands = commands.lock().unwrap().clone();
+        let get_channel = commands
+            .iter()
+            .position(|cmd| cmd == "GET_CHANNEL")
+            .unwrap();
+        // VOICE_CHANNEL_SELECT, then the five events of the channel.
+        let subscribed_before = commands[..get_channel]
+            .iter()
+            .filter(|cmd| *cmd == "SUBSCRIBE")
+            .count();
+        assert_eq!(subscribed_before, 6);
+
+        flush_events(&mut client);
+        let snapshot = watcher.snapshot();
+        assert_eq!(snapshot.channel_id.as_deref(), Some("10"));
+        assert_eq!(snapshot.guild_id.as_deref(), Some("20"));
+        assert_eq!(
+            member_states(&watcher),
+            [("alice".to_owned(), false), ("bob".to_owned(), true)]
+        );
+    }
+
+    #[test]
+    fn voice_watcher_forgets_members_leaving_during_the_fetch() {
+        let (flushed_tx, flushed_rx) = mpsc::channel();
+        let discord = FakeDiscord::new(move |request| {
+            let channel = voice_channel(
+                "10",
+                &[voice_state("alice", false), voice_state("bob", false)],
+            );
+            match (request["cmd"].as_str(), request["evt"].as_str()) {
+                (Some("GET_SELECTED_VOICE_CHANNEL"), _) => vec![reply(request, channel)],
+                // Bob leaves once the watcher is subscribed, the response still lists him.
+                (Some("SUBSCRIBE"), Some("SPEAKING_STOP")) => vec![
+                    dispatch("VOICE_STATE_DELETE", voice_state("bob", false)),
+                    dispatch("FLUSHED", json!({})),
+                    reply(request, json!({ "evt": request["evt"] })),
+                ],
+                (Some("SUBSCRIBE"), _) => vec![reply(request, json!({ "evt": request["evt"] }))],
+                // Answers only once the dispatcher went through bob's departure.
+                (Some("GET_CHANNEL"), _) => {
+                    flushed_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+                    vec![reply(request, channel)]
                 }
-                _ => (),
+                (Some("GET_GUILDS"), _) => flushed(request),
+                _ => panic!("unexpected command {request}"),
             }
+        });
+        let mut client = connected_client(discord);
+        let _flushed = client
+            .on_any(move |payload| {
+                if matches!(
+                    payload,
+                    Payload::InComingCommand { evt: Some(Event::Unknown(ref name)), .. }
+                        if name == "FLUSHED"
+                ) {
+                    let _ = flushed_tx.send(());
+                }
+            })
+            .unwrap();
 
-            Ok(())
-        } else {
-            Err(IpcError::WriteError(String::from(
-                "There is no valid source provided, please open a source first",
-            )))
+        let watcher = VoiceChannelWatcher::new(&mut client).unwrap();
+
+        flush_events(&mut client);
+        assert_eq!(member_states(&watcher), [("alice".to_owned(), false)]);
+        let changes: Vec<_> = watcher
+            .changes()
+            .map(|change| match change {
+                VoiceChange::ChannelChanged(id) => format!("channel {}", id.unwrap()),
+                VoiceChange::MemberJoined(member) => format!("joined {}", member.user.id),
+                VoiceChange::MemberLeft(id) => format!("left {id}"),
+                change => format!("{change:?}"),
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            ["channel 10", "joined alice", "joined bob", "left bob"]
+        );
+    }
+
+    #[test]
+    fn voice_watcher_ignores_events_of_the_previous_channel() {
+        let mut moved = false;
+        let discord = FakeDiscord::new(move |request| match request["cmd"].as_str() {
+            Some("GET_SELECTED_VOICE_CHANNEL") => vec![reply(
+                request,
+                voice_channel("10", &[voice_state("alice", false)]),
+            )],
+            Some("SUBSCRIBE" | "UNSUBSCRIBE") => {
+                vec![reply(request, json!({ "evt": request["evt"] }))]
+            }
+            Some("GET_CHANNEL") => match request["args"]["channel_id"].as_str() {
+                Some("10") => vec![reply(
+                    request,
+                    voice_channel("10", &[voice_state("alice", false)]),
+                )],
+                _ => vec![reply(
+                    request,
+                    voice_channel("11", &[voice_state("dave", false)]),
+                )],
+            },
+            // The user moves to channel 11 right as carol joins channel 10.
+            Some("GET_GUILDS") if !moved => {
+                moved = true;
+                vec![
+                    dispatch(
+                        "VOICE_CHANNEL_SELECT",
+                        json!({ "channel_id": "11", "guild_id": "20" }),
+                    ),
+                    dispatch("VOICE_STATE_CREATE", voice_state("carol", false)),
+                    reply(request, json!({ "guilds": [] })),
+                ]
+            }
+            Some("GET_GUILDS") => flushed(request),
+            _ => panic!("unexpected command {request}"),
+        });
+        let mut client = connected_client(discord);
+        let mut watcher = VoiceChannelWatcher::new(&mut client).unwrap();
+        // Holds the dispatcher on VOICE_CHANNEL_SELECT, so that carol's event is still queued
+        // while the watcher moves to channel 11.
+        let (reached_tx, reached_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let _blocker = client
+            .on(Event::VoiceChannelSelect, move |_| {
+                let _ = reached_tx.send(());
+                let _ = release_rx.recv();
+            })
+            .unwrap();
+
+        client.get_guilds().unwrap();
+        reached_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        watcher.sync(&mut client).unwrap();
+        release_tx.send(()).unwrap();
+
+        flush_events(&mut client);
+        assert_eq!(watcher.snapshot().channel_id.as_deref(), Some("11"));
+        assert_eq!(member_states(&watcher), [("dave".to_owned(), false)]);
+    }
+
+    /// A Discord reporting the state of every `SET_ACTIVITY` it receives, along with when it
+    /// received it. The first `failures` ones are answered with a critical error.
+    fn presence_recorder(mut failures: usize) -> (FakeDiscord, mpsc::Receiver<(Instant, String)>) {
+        let (tx, rx) = mpsc::channel();
+        let discord = FakeDiscord::new(move |request| match request["cmd"].as_str() {
+            Some("SET_ACTIVITY") => {
+                let state = request["args"]["activity"]["state"]
+                    .as_str()
+                    .unwrap_or_default();
+                let _ = tx.send((Instant::now(), state.to_owned()));
+                if failures > 0 {
+                    failures -= 1;
+                    return vec![json!({ "code": 1000, "message": "Closed" })];
+                }
+                vec![reply(request, request["args"]["activity"].clone())]
+            }
+            _ => panic!("unexpected command {request}"),
+        });
+        (discord, rx)
+    }
+
+    fn next_presence(sent: &mpsc::Receiver<(Instant, String)>) -> (Instant, String) {
+        sent.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+
+    #[test]
+    fn presence_updates_are_coalesced_while_rate_limited() {
+        let (discord, sent) = presence_recorder(0);
+        let window = Duration::from_millis(200);
+        let manager = PresenceManager::with_rate_limit(connected_client(discord), 1, window);
+
+        manager.update(Activity::new().set_state("One")).unwrap();
+        assert_eq!(next_presence(&sent).1, "One");
+        for state in ["Two", "Three", "Four"] {
+            manager.update(Activity::new().set_state(state)).unwrap();
         }
+
+        assert_eq!(next_presence(&sent).1, "Four");
+        assert!(sent.recv_timeout(window * 2).is_err());
+    }
+
+    #[test]
+    fn presence_updates_stay_within_the_rate_limit() {
+        let (discord, sent) = presence_recorder(0);
+        let window = Duration::from_millis(500);
+        let manager = PresenceManager::with_rate_limit(connected_client(discord), 5, window);
+
+        let start = Instant::now();
+        let sent_at: Vec<_> = (0..6)
+            .map(|i| {
+                manager
+                    .update(Activity::new().set_state(format!("Update {i}")))
+                    .unwrap();
+                let (at, state) = next_presence(&sent);
+                assert_eq!(state, format!("Update {i}"));
+                at.duration_since(start)
+            })
+            .collect();
+
+        assert!(sent_at[4] < window);
+        assert!(sent_at[5] >= window);
+    }
+
+    #[test]
+    fn identical_presence_updates_are_skipped() {
+        let (discord, sent) = presence_recorder(0);
+        let window = Duration::from_millis(100);
+        let manager = PresenceManager::with_rate_limit(connected_client(discord), 1, window);
+
+        manager.update(Activity::new().set_state("One")).unwrap();
+        assert_eq!(next_presence(&sent).1, "One");
+        manager.update(Activity::new().set_state("One")).unwrap();
+        assert!(sent.recv_timeout(window * 3).is_err());
+
+        manager.update(Activity::new().set_state("Two")).unwrap();
+        assert_eq!(next_presence(&sent).1, "Two");
+    }
+
+    #[test]
+    fn failed_presence_updates_are_retried() {
+        let (discord, sent) = presence_recorder(1);
+        let manager = PresenceManager::with_rate_limit(
+            connected_client(discord),
+            5,
+            Duration::from_millis(100),
+        );
+
+        manager.update(Activity::new().set_state("One")).unwrap();
+
+        assert_eq!(next_presence(&sent).1, "One");
+        assert_eq!(next_presence(&sent).1, "One");
+        assert!(matches!(
+            manager.take_error(),
+            Some(IpcError::CriticalError(message)) if message == "Closed"
+        ));
+        assert!(sent.recv_timeout(Duration::from_millis(300)).is_err());
     }
 }
@@ -1,18 +1,141 @@
+#[macro_use]
+mod macros;
+
 mod activity;
+mod auth;
+mod commands;
 mod consts;
 mod errors;
 mod events;
-pub use activity::Activity;
+mod guild;
+pub use activity::{
+    Activity,
+    ActivityInvite,
+    ActivitySecret,
+    JoinRequest,
+};
+pub use auth::{
+    Application,
+    AuthenticateResponse,
+    AuthorizeOptions,
+    AuthorizeResponse,
+    FileTokenStore,
+    OAuthToken,
+    Prompt,
+    StoredToken,
+    TokenExchange,
+    TokenRefresh,
+    TokenStore,
+};
+pub use commands::Command;
 pub use errors::*;
-pub use events::Event;
+pub use events::{
+    Event,
+    EventPayload,
+    Ready,
+    RpcConfig,
+    Subscription,
+};
+pub use guild::{
+    Channel,
+    ChannelType,
+    Guild,
+    GuildStatus,
+    PartialChannel,
+    PartialGuild,
+};
 mod ipc;
+mod lobby;
+mod message;
+mod overlay;
 mod payload;
+mod presence;
+mod store;
+mod user;
+mod voice;
+mod voice_watcher;
 use errors::PacketResult;
 pub use ipc::{
     DiscordIpcClient,
     IpcClient,
+    IpcSource,
+};
+pub use lobby::{
+    Lobby,
+    LobbyDelete,
+    LobbyMember,
+    LobbyMemberEvent,
+    LobbyMessage,
+    LobbyOptions,
+    LobbySearch,
+    LobbySearchCast,
+    LobbySearchComparison,
+    LobbySearchDistance,
+    LobbySearchFilter,
+    LobbySearchSort,
+    LobbyType,
+};
+pub use message::{
+    Attachment,
+    DeletedMessage,
+    Embed,
+    EmbedField,
+    Message,
+    MessageDelete,
+    MessageEvent,
+    Notification,
+};
+pub use overlay::{
+    ActivityActionType,
+    OverlayState,
 };
 pub use payload::*;
+pub use presence::PresenceManager;
+pub use store::{
+    Entitlement,
+    EntitlementType,
+    Sku,
+    SkuPrice,
+    SkuType,
+    UserAchievement,
+};
+pub use user::{
+    Relationship,
+    RelationshipType,
+    Status,
+    User,
+    UserActivity,
+    UserPresence,
+};
+pub use voice::{
+    AvailableDevice,
+    Device,
+    DeviceInfo,
+    DeviceType,
+    KeyType,
+    Pan,
+    SelectVoiceChannelOptions,
+    ShortcutChange,
+    ShortcutKeyCombo,
+    Speaking,
+    UserVoiceSettings,
+    UserVoiceState,
+    VoiceChannelSelect,
+    VoiceConnectionStatus,
+    VoiceDeviceSettings,
+    VoiceModeSettings,
+    VoiceModeType,
+    VoicePing,
+    VoiceSettings,
+    VoiceState,
+};
+
+pub use voice_watcher::{
+    VoiceChange,
+    VoiceChannelSnapshot,
+    VoiceChannelWatcher,
+    VoiceMember,
+};
 
 #[derive(Debug)]
 pub struct Header {
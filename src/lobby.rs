@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::user::User;
+
+int_enum! {
+    pub enum LobbyType: u8 {
+        Private = 1,
+        Public = 2,
+        _ => Unknown,
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LobbyMember {
+    pub user: User,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Lobby {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: LobbyType,
+    pub owner_id: String,
+    pub secret: String,
+    pub capacity: u32,
+    pub locked: bool,
+    #[serde(default)]
+    pub application_id: Option<String>,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    #[serde(default)]
+    pub members: Vec<LobbyMember>,
+}
+
+/// Arguments of `CREATE_LOBBY` and `UPDATE_LOBBY`. Unset fields keep Discord's default, or the
+/// current value when updating.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct LobbyOptions {
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub kind: Option<LobbyType>,
+    /// Transfers the lobby, only used when updating.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capacity: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locked: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+int_enum! {
+    pub enum LobbySearchComparison: i8 {
+        LessThanOrEqual = -2,
+        LessThan = -1,
+        Equal = 0,
+        GreaterThan = 1,
+        GreaterThanOrEqual = 2,
+        NotEqual = 3,
+    }
+}
+
+int_enum! {
+    /// How metadata values are compared.
+    pub enum LobbySearchCast: u8 {
+        String = 1,
+        Number = 2,
+    }
+}
+
+int_enum! {
+    pub enum LobbySearchDistance: u8 {
+        Local = 0,
+        Default = 1,
+        Extended = 2,
+        Global = 3,
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct LobbySearchFilter {
+    /// A metadata key, prefixed with `metadata.`, or one of the lobby fields such as `owner_id`.
+    pub key: String,
+    pub value: String,
+    pub cmp: LobbySearchComparison,
+    pub cast: LobbySearchCast,
+}
+
+/// Sorts results by how close `key` is to `near_value`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct LobbySearchSort {
+    pub key: String,
+    pub cast: LobbySearchCast,
+    pub near_value: String,
+}
+
+/// Arguments of `SEARCH_LOBBIES`.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct LobbySearch {
+    pub filter: Vec<LobbySearchFilter>,
+    pub sort: Vec<LobbySearchSort>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance: Option<LobbySearchDistance>,
+}
+
+/// Data of the `LOBBY_DELETE` event.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LobbyDelete {
+    pub id: String,
+    #[serde(default)]
+    pub reason: Option<u32>,
+}
+
+/// Data of the `LOBBY_MEMBER_CONNECT`, `LOBBY_MEMBER_DISCONNECT` and `LOBBY_MEMBER_UPDATE` events.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LobbyMemberEvent {
+    pub lobby_id: String,
+    pub member: LobbyMember,
+}
+
+/// Data of the `LOBBY_MESSAGE` event.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LobbyMessage {
+    pub lobby_id: String,
+    pub sender_id: String,
+    pub data: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn search_enums_serialize_as_integers() {
+        let filter = LobbySearchFilter {
+            key: "metadata.level".to_owned(),
+            value: "10".to_owned(),
+            cmp: LobbySearchComparison::LessThanOrEqual,
+            cast: LobbySearchCast::Number,
+        };
+        assert_eq!(
+            serde_json::to_value(filter).unwrap(),
+            json!({ "key": "metadata.level", "value": "10", "cmp": -2, "cast": 2 })
+        );
+        assert_eq!(
+            serde_json::to_value(LobbySearchDistance::Global).unwrap(),
+            json!(3)
+        );
+    }
+
+    #[test]
+    fn unknown_lobby_types_round_trip() {
+        let kind: LobbyType = serde_json::from_value(json!(9)).unwrap();
+        assert_eq!(kind, LobbyType::Unknown(9));
+        assert_eq!(serde_json::to_value(kind).unwrap(), json!(9));
+    }
+}
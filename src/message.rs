@@ -0,0 +1,108 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::user::User;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EmbedField {
+    pub name: String,
+    pub value: String,
+    #[serde(default)]
+    pub inline: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Embed {
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(rename = "type", default)]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub timestamp: Option<String>,
+    #[serde(default)]
+    pub color: Option<u32>,
+    #[serde(default)]
+    pub fields: Vec<EmbedField>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    pub id: String,
+    pub filename: String,
+    pub size: u64,
+    pub url: String,
+    #[serde(default)]
+    pub proxy_url: Option<String>,
+    #[serde(default)]
+    pub content_type: Option<String>,
+    #[serde(default)]
+    pub width: Option<u32>,
+    #[serde(default)]
+    pub height: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Message {
+    pub id: String,
+    pub author: User,
+    /// The author's nickname in the guild, if any.
+    #[serde(default)]
+    pub nick: Option<String>,
+    #[serde(default)]
+    pub content: String,
+    /// ISO 8601 dates.
+    #[serde(default)]
+    pub timestamp: Option<String>,
+    #[serde(default)]
+    pub edited_timestamp: Option<String>,
+    #[serde(default)]
+    pub tts: bool,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub mention_everyone: bool,
+    #[serde(default)]
+    pub mentions: Vec<User>,
+    #[serde(default)]
+    pub mention_roles: Vec<String>,
+    #[serde(default)]
+    pub embeds: Vec<Embed>,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+}
+
+/// Data of the `MESSAGE_CREATE` and `MESSAGE_UPDATE` events.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MessageEvent {
+    pub channel_id: String,
+    pub message: Message,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DeletedMessage {
+    pub id: String,
+}
+
+/// Data of the `MESSAGE_DELETE` event, only the id of the message is left.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MessageDelete {
+    pub channel_id: String,
+    pub message: DeletedMessage,
+}
+
+/// Data of the `NOTIFICATION_CREATE` event.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub channel_id: String,
+    pub message: Message,
+    #[serde(default)]
+    pub icon_url: Option<String>,
+    pub title: String,
+    pub body: String,
+}
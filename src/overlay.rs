@@ -0,0 +1,23 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+/// Data of the `OVERLAY` and `OVERLAY_UPDATE` events.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OverlayState {
+    /// Whether the user has the overlay enabled for the game.
+    #[serde(default)]
+    pub enabled: bool,
+    /// A locked overlay is closed and doesn't take input.
+    #[serde(default)]
+    pub locked: bool,
+}
+
+int_enum! {
+    pub enum ActivityActionType: u8 {
+        Join = 1,
+        Spectate = 2,
+        _ => Unknown,
+    }
+}
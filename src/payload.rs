@@ -7,6 +7,7 @@ use errors::{
 use serde::{
     de::{
         self,
+        DeserializeOwned,
         MapAccess,
         Visitor,
     },
@@ -20,6 +21,7 @@ use serde_json::{
 };
 
 use crate::{
+    commands::Command,
     errors,
     events::Event,
 };
@@ -31,14 +33,14 @@ pub enum Payload {
         client_id: String,
     },
     OutGoingCommand {
-        cmd: String,
-        nonce: i64,
+        cmd: Command,
+        nonce: String,
         args: serde_json::Value,
         evt: Option<Event>,
     },
     InComingCommand {
-        cmd: String,
-        nonce: i64,
+        cmd: Command,
+        nonce: String,
         args: Option<serde_json::Value>,
         data: serde_json::Value,
         evt: Option<Event>,
@@ -75,6 +77,14 @@ impl Payload {
     pub fn to_bytes(&self) -> PayloadResult<Vec<u8>> {
         Ok(serde_json::to_vec(&self.to_json()?)?)
     }
+
+    /// Deserializes the `data` of an incoming command or event into one of the typed models.
+    pub fn data<T: DeserializeOwned>(&self) -> PayloadResult<T> {
+        match self {
+            Payload::InComingCommand { data, .. } => Ok(T::deserialize(data)?),
+            _ => Err(PayloadError::NoDataError),
+        }
+    }
 }
 
 impl<'de> Deserialize<'de> for Payload {
@@ -129,7 +139,7 @@ impl<'de> Deserialize<'de> for Payload {
                             if nonce.is_some() {
                                 return Err(de::Error::duplicate_field("nonce"));
                             }
-                            nonce = Some(map.next_value::<Option<i64>>()?.unwrap_or_default());
+                            nonce = Some(map.next_value::<Option<String>>()?.unwrap_or_default());
                         }
                         "args" => {
                             if args.is_some() {
@@ -170,7 +180,7 @@ impl<'de> Deserialize<'de> for Payload {
                 if let (Some(v), Some(client_id)) = (v, client_id) {
                     Ok(Payload::Handshake { v, client_id })
                 } else if let (Some(cmd), Some(nonce), Some(data), Some(evt)) =
-                    (cmd.clone(), nonce, data, evt.clone())
+                    (cmd.clone(), nonce.clone(), data, evt.clone())
                 {
                     Ok(Payload::InComingCommand {
                         cmd,
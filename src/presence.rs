@@ -0,0 +1,194 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{
+            AtomicBool,
+            Ordering,
+        },
+        Arc,
+        Condvar,
+        Mutex,
+    },
+    thread,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use serde_json::Value;
+
+use crate::{
+    consts::{
+        PRESENCE_RATE_LIMIT,
+        PRESENCE_RATE_WINDOW,
+    },
+    errors::{
+        IpcError,
+        IpcResult,
+    },
+    Activity,
+    DiscordIpcClient,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Presence {
+    Activity(Value),
+    Cleared,
+}
+
+struct PresenceState {
+    pending: Option<Presence>,
+    last_sent: Option<Presence>,
+    sent_at: VecDeque<Instant>,
+    error: Option<IpcError>,
+}
+
+/// Sits in front of [`DiscordIpcClient::set_activity`] and keeps it within Discord's rate limit
+/// (about 5 `SET_ACTIVITY` calls per 20 seconds).
+///
+/// Updates are sent from a background thread. While the limit is reached only the latest update
+/// is kept, and it is sent as soon as the window opens again. Updates identical to the last sent
+/// presence are skipped, and updates that fail to reach Discord are retried until they succeed or
+/// get replaced.
+pub struct PresenceManager {
+    client: Arc<Mutex<DiscordIpcClient>>,
+    state: Arc<(Mutex<PresenceState>, Condvar)>,
+    running: Arc<AtomicBool>,
+}
+
+impl PresenceManager {
+    pub fn new(client: DiscordIpcClient) -> Self {
+        Self::with_rate_limit(client, PRESENCE_RATE_LIMIT, PRESENCE_RATE_WINDOW)
+    }
+
+    /// Allows at most `limit` updates to be sent within any `window`.
+    pub fn with_rate_limit(client: DiscordIpcClient, limit: usize, window: Duration) -> Self {
+        let manager = Self {
+            client: Arc::new(Mutex::new(client)),
+            state: Arc::new((
+                Mutex::new(PresenceState {
+                    pending: None,
+                    last_sent: None,
+                    sent_at: VecDeque::with_capacity(limit),
+                    error: None,
+                }),
+                Condvar::new(),
+            )),
+            running: Arc::new(AtomicBool::new(true)),
+        };
+
+        let client = Arc::clone(&manager.client);
+        let state = Arc::clone(&manager.state);
+        let running = Arc::clone(&manager.running);
+        thread::spawn(move || run(client, state, running, limit.max(1), window));
+        manager
+    }
+
+    /// Queues `activity`, replacing any update that is still waiting to be sent. The activity is
+    /// validated right away so that errors are not lost in the background thread.
+    pub fn update(&self, activity: Activity) -> IpcResult<()> {
+        activity.validate().map_err(IpcError::InvalidActivity)?;
+        self.queue(Presence::Activity(serde_json::to_value(activity)?));
+        Ok(())
+    }
+
+    pub fn clear(&self) {
+        self.queue(Presence::Cleared);
+    }
+
+    /// Returns the error of the last failed update, if any.
+    pub fn take_error(&self) -> Option<IpcError> {
+        self.state.0.lock().unwrap().error.take()
+    }
+
+    /// The wrapped client, for everything that isn't a presence update.
+    ///
+    /// Avoid calling [`DiscordIpcClient::poll`] through it: `poll` keeps the client locked until
+    /// Discord sends something, and presence updates wait for the lock in the meantime. Events are
+    /// also read while a command waits for its response, including the presence updates.
+    pub fn client(&self) -> Arc<Mutex<DiscordIpcClient>> {
+        Arc::clone(&self.client)
+    }
+
+    pub fn stop(&self) {
+        let (lock, cvar) = &*self.state;
+        let _guard = lock.lock().unwrap();
+        self.running.store(false, Ordering::SeqCst);
+        cvar.notify_all();
+    }
+
+    fn queue(&self, presence: Presence) {
+        let (lock, cvar) = &*self.state;
+        lock.lock().unwrap().pending = Some(presence);
+        cvar.notify_all();
+    }
+}
+
+impl Drop for PresenceManager {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn run(
+    client: Arc<Mutex<DiscordIpcClient>>,
+    state: Arc<(Mutex<PresenceState>, Condvar)>,
+    running: Arc<AtomicBool>,
+    limit: usize,
+    window: Duration,
+) {
+    let (lock, cvar) = &*state;
+    let mut guard = lock.lock().unwrap();
+    while running.load(Ordering::SeqCst) {
+        let Some(presence) = guard.pending.take() else {
+            guard = cvar.wait(guard).unwrap();
+            continue;
+        };
+        if guard.last_sent.as_ref() == Some(&presence) {
+            continue;
+        }
+
+        let now = Instant::now();
+        while guard
+            .sent_at
+            .front()
+            .is_some_and(|sent| now.duration_since(*sent) >= window)
+        {
+            guard.sent_at.pop_front();
+        }
+        if let Some(oldest) = guard.sent_at.front().copied() {
+            if guard.sent_at.len() >= limit {
+                guard.pending = Some(presence);
+                let timeout = window.saturating_sub(now.duration_since(oldest));
+                guard = cvar.wait_timeout(guard, timeout).unwrap().0;
+                continue;
+            }
+        }
+        guard.sent_at.push_back(now);
+        drop(guard);
+
+        let result = {
+            let mut client = client.lock().unwrap();
+            match &presence {
+                Presence::Activity(activity) => client.set_activity_value(activity.clone()),
+                Presence::Cleared => client.clear_activity(),
+            }
+        };
+
+        guard = lock.lock().unwrap();
+        match result {
+            Ok(()) => guard.last_sent = Some(presence),
+            // Discord rejecting the presence won't change on a retry.
+            Err(err @ IpcError::Rpc(_)) => guard.error = Some(err),
+            Err(err) => {
+                // The update didn't go through: give the slot back and retry it after a pause,
+                // unless a newer one has been queued in the meantime.
+                guard.error = Some(err);
+                guard.sent_at.pop_back();
+                guard.pending.get_or_insert(presence);
+                guard = cvar.wait_timeout(guard, window / limit as u32).unwrap().0;
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+int_enum! {
+    pub enum EntitlementType: u8 {
+        Purchase = 1,
+        PremiumSubscription = 2,
+        DeveloperGift = 3,
+        TestModePurchase = 4,
+        FreePurchase = 5,
+        UserGift = 6,
+        PremiumPurchase = 7,
+        _ => Unknown,
+    }
+}
+
+/// Data of `GET_ENTITLEMENTS` and the `ENTITLEMENT_CREATE` and `ENTITLEMENT_DELETE` events.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Entitlement {
+    pub id: String,
+    pub sku_id: String,
+    #[serde(default)]
+    pub application_id: Option<String>,
+    #[serde(default)]
+    pub user_id: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: EntitlementType,
+}
+
+int_enum! {
+    pub enum SkuType: u8 {
+        Application = 1,
+        Dlc = 2,
+        Consumable = 3,
+        Bundle = 4,
+        _ => Unknown,
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SkuPrice {
+    /// In the smallest unit of `currency`, e.g. cents.
+    pub amount: u64,
+    pub currency: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Sku {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: SkuType,
+    #[serde(default)]
+    pub price: Option<SkuPrice>,
+}
+
+/// Data of `GET_USER_ACHIEVEMENTS` and the `USER_ACHIEVEMENT_UPDATE` event.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct UserAchievement {
+    pub user_id: String,
+    pub achievement_id: String,
+    /// From 0 to 100.
+    pub percent_complete: u8,
+    /// ISO 8601 date at which the achievement was unlocked.
+    #[serde(default)]
+    pub unlocked_at: Option<String>,
+}
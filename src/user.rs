@@ -0,0 +1,83 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct User {
+    pub id: String,
+    pub username: String,
+    #[serde(default)]
+    pub discriminator: Option<String>,
+    #[serde(default)]
+    pub global_name: Option<String>,
+    #[serde(default)]
+    pub avatar: Option<String>,
+    #[serde(default)]
+    pub bot: bool,
+    #[serde(default)]
+    pub flags: Option<u64>,
+    #[serde(default)]
+    pub premium_type: Option<u8>,
+}
+
+int_enum! {
+    pub enum RelationshipType: u8 {
+        None = 0,
+        Friend = 1,
+        Blocked = 2,
+        PendingIncoming = 3,
+        PendingOutgoing = 4,
+        Implicit = 5,
+        _ => Unknown,
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Online,
+    Idle,
+    Dnd,
+    Invisible,
+    Offline,
+}
+
+/// The activity a user is currently in, as shown on their profile.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserActivity {
+    pub name: String,
+    #[serde(rename = "type", default)]
+    pub kind: Option<u8>,
+    #[serde(default)]
+    pub application_id: Option<String>,
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default)]
+    pub details: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserPresence {
+    pub status: Status,
+    #[serde(default)]
+    pub activity: Option<UserActivity>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Relationship {
+    #[serde(rename = "type")]
+    pub kind: RelationshipType,
+    pub user: User,
+    pub presence: UserPresence,
+}
+
+impl Relationship {
+    pub fn is_online(&self) -> bool {
+        !matches!(self.presence.status, Status::Offline | Status::Invisible)
+    }
+
+    pub fn is_in_game(&self) -> bool {
+        self.presence.activity.is_some()
+    }
+}
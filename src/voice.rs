@@ -0,0 +1,297 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::{
+    consts::USER_VOLUME_MAX,
+    errors::{
+        IpcError,
+        IpcResult,
+    },
+    user::User,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Pan {
+    pub left: f32,
+    pub right: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct VoiceState {
+    #[serde(default)]
+    pub mute: bool,
+    #[serde(default)]
+    pub deaf: bool,
+    #[serde(default)]
+    pub self_mute: bool,
+    #[serde(default)]
+    pub self_deaf: bool,
+    #[serde(default)]
+    pub suppress: bool,
+}
+
+/// A member of a voice channel, as found in `voice_states` and the `VOICE_STATE_*` events.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserVoiceState {
+    pub user: User,
+    #[serde(default)]
+    pub nick: Option<String>,
+    #[serde(default)]
+    pub volume: Option<f32>,
+    /// Muted locally by the current user.
+    #[serde(default)]
+    pub mute: bool,
+    #[serde(default)]
+    pub pan: Option<Pan>,
+    pub voice_state: VoiceState,
+}
+
+/// Local voice settings for another user, sent with `SET_USER_VOICE_SETTINGS`. Unset fields are
+/// left unchanged.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserVoiceSettings {
+    pub user_id: String,
+    /// Left and right volume, each from 0.0 to 1.0.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pan: Option<Pan>,
+    /// From 0 to 200, 100 being the default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volume: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mute: Option<bool>,
+}
+
+impl UserVoiceSettings {
+    pub fn new(user_id: impl Into<String>) -> Self {
+        Self {
+            user_id: user_id.into(),
+            pan: None,
+            volume: None,
+            mute: None,
+        }
+    }
+
+    pub fn set_pan(mut self, left: f32, right: f32) -> Self {
+        self.pan = Some(Pan { left, right });
+        self
+    }
+
+    pub fn set_volume(mut self, volume: f32) -> Self {
+        self.volume = Some(volume);
+        self
+    }
+
+    pub fn set_mute(mut self, mute: bool) -> Self {
+        self.mute = Some(mute);
+        self
+    }
+
+    pub(crate) fn validate(&self) -> IpcResult<()> {
+        if let Some(volume) = self.volume {
+            if !(0.0..=USER_VOLUME_MAX).contains(&volume) {
+                return Err(IpcError::InvalidArgument(format!(
+                    "volume must be between 0 and {}, got {}",
+                    USER_VOLUME_MAX, volume
+                )));
+            }
+        }
+        if let Some(Pan { left, right }) = self.pan {
+            if !(0.0..=1.0).contains(&left) || !(0.0..=1.0).contains(&right) {
+                return Err(IpcError::InvalidArgument(format!(
+                    "pan must be between 0.0 and 1.0 on both sides, got {} and {}",
+                    left, right
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Options of `SELECT_VOICE_CHANNEL`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SelectVoiceChannelOptions {
+    /// How long to wait for the channel to become available, in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u32>,
+    /// Moves the user even if they are already in another voice channel.
+    pub force: bool,
+    /// Also navigates the Discord client to the channel.
+    pub navigate: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AvailableDevice {
+    pub id: String,
+    pub name: String,
+}
+
+/// Input or output device settings. `available_devices` is only filled in by Discord and ignored
+/// when setting.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct VoiceDeviceSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<String>,
+    /// 0 to 100 for input, 0 to 200 for output.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volume: Option<f32>,
+    #[serde(skip_serializing)]
+    pub available_devices: Option<Vec<AvailableDevice>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum VoiceModeType {
+    PushToTalk,
+    VoiceActivity,
+}
+
+int_enum! {
+    pub enum KeyType: u8 {
+        KeyboardKey = 0,
+        MouseButton = 1,
+        KeyboardModifierKey = 2,
+        GamepadButton = 3,
+        _ => Unknown,
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShortcutKeyCombo {
+    #[serde(rename = "type")]
+    pub kind: KeyType,
+    pub code: u32,
+    pub name: String,
+}
+
+/// Data of the `CAPTURE_SHORTCUT_CHANGE` event, sent while a shortcut is being captured.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShortcutChange {
+    pub shortcut: Vec<ShortcutKeyCombo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct VoiceModeSettings {
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub kind: Option<VoiceModeType>,
+    /// Whether the voice activity threshold is set automatically.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_threshold: Option<bool>,
+    /// Voice activity threshold in dB, from -100 to 0.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f32>,
+    /// Push to talk shortcut.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shortcut: Option<Vec<ShortcutKeyCombo>>,
+    /// Push to talk release delay in milliseconds, from 0 to 2000.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delay: Option<f32>,
+}
+
+/// The user's voice settings, as returned by `GET_VOICE_SETTINGS` and `VOICE_SETTINGS_UPDATE`.
+///
+/// Every field is optional so the same struct can be used for partial updates: only the fields
+/// that are set get sent with `SET_VOICE_SETTINGS`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct VoiceSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input: Option<VoiceDeviceSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<VoiceDeviceSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<VoiceModeSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub automatic_gain_control: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub echo_cancellation: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub noise_suppression: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qos: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub silence_warning: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deaf: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mute: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceType {
+    AudioInput,
+    AudioOutput,
+    VideoInput,
+}
+
+/// Vendor or model of a certified device.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub name: String,
+    pub url: String,
+}
+
+/// A hardware certified device, reported with `SET_CERTIFIED_DEVICES`. The audio processing flags
+/// only apply to audio input devices.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Device {
+    #[serde(rename = "type")]
+    pub kind: DeviceType,
+    /// The device's Windows UUID.
+    pub id: String,
+    pub vendor: DeviceInfo,
+    pub model: DeviceInfo,
+    /// UUIDs of related devices.
+    #[serde(default)]
+    pub related: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub echo_cancellation: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub noise_suppression: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub automatic_gain_control: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hardware_mute: Option<bool>,
+}
+
+/// Data of the `VOICE_CHANNEL_SELECT` event, both ids are `None` when leaving voice.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct VoiceChannelSelect {
+    #[serde(default)]
+    pub channel_id: Option<String>,
+    #[serde(default)]
+    pub guild_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct VoicePing {
+    /// Unix timestamp in milliseconds.
+    pub time: u64,
+    /// Ping in milliseconds.
+    pub value: f32,
+}
+
+/// Data of the `VOICE_CONNECTION_STATUS` event.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct VoiceConnectionStatus {
+    /// One of `DISCONNECTED`, `AWAITING_ENDPOINT`, `AUTHENTICATING`, `CONNECTING`, `CONNECTED`,
+    /// `VOICE_DISCONNECTED`, `VOICE_CONNECTING`, `VOICE_CONNECTED`, `NO_ROUTE` or `ICE_CHECKING`.
+    pub state: String,
+    #[serde(default)]
+    pub hostname: Option<String>,
+    #[serde(default)]
+    pub pings: Vec<VoicePing>,
+    #[serde(default)]
+    pub average_ping: Option<f32>,
+    #[serde(default)]
+    pub last_ping: Option<f32>,
+}
+
+/// Data of the `SPEAKING_START` and `SPEAKING_STOP` events.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Speaking {
+    pub user_id: String,
+    #[serde(default)]
+    pub channel_id: Option<String>,
+}
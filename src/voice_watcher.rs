@@ -0,0 +1,264 @@
+use std::sync::{
+    mpsc,
+    Arc,
+    Mutex,
+};
+
+use serde_json::json;
+
+use crate::{
+    errors::IpcResult,
+    events::{
+        Event,
+        EventPayload,
+        Subscription,
+    },
+    user::User,
+    voice::{
+        UserVoiceState,
+        VoiceState,
+    },
+    DiscordIpcClient,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoiceMember {
+    pub user: User,
+    pub nick: Option<String>,
+    pub voice_state: VoiceState,
+    /// Muted locally by the current user.
+    pub mute: bool,
+    pub volume: Option<f32>,
+    pub speaking: bool,
+}
+
+impl VoiceMember {
+    fn from_voice_state(state: UserVoiceState) -> Self {
+        Self {
+            user: state.user,
+            nick: state.nick,
+            voice_state: state.voice_state,
+            mute: state.mute,
+            volume: state.volume,
+            speaking: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VoiceChannelSnapshot {
+    pub channel_id: Option<String>,
+    pub guild_id: Option<String>,
+    pub members: Vec<VoiceMember>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VoiceChange {
+    /// The user joined another voice channel, or left voice if `None`. The members of the new
+    /// channel follow as `MemberJoined` changes.
+    ChannelChanged(Option<String>),
+    MemberJoined(VoiceMember),
+    MemberUpdated(VoiceMember),
+    MemberLeft(String),
+    SpeakingStarted(String),
+    SpeakingStopped(String),
+}
+
+struct WatchedChannel {
+    snapshot: VoiceChannelSnapshot,
+    /// Events received while the channel's members are being fetched, applied on top of them.
+    pending: Option<Vec<EventPayload>>,
+}
+
+/// Keeps a live model of the voice channel the user is in: its members, their mute and deaf
+/// state and who is speaking.
+///
+/// The watcher subscribes to the channel's `VOICE_STATE_*` and `SPEAKING_*` events and follows
+/// `VOICE_CHANNEL_SELECT`. Since it can't use the client from within an event callback, moving to
+/// another channel only takes effect on the next [`VoiceChannelWatcher::sync`]. A typical loop
+/// calls [`DiscordIpcClient::poll`], then `sync`, then reads [`VoiceChannelWatcher::snapshot`]
+/// or [`VoiceChannelWatcher::changes`].
+///
+/// Events are applied on the client's event dispatcher thread, so the model may lag a moment
+/// behind the frame `poll` just read.
+pub struct VoiceChannelWatcher {
+    model: Arc<Mutex<WatchedChannel>>,
+    changes_tx: mpsc::Sender<VoiceChange>,
+    changes_rx: mpsc::Receiver<VoiceChange>,
+    selected_rx: mpsc::Receiver<Option<String>>,
+    channel_subscriptions: Vec<Subscription>,
+    _select_subscription: Subscription,
+}
+
+impl VoiceChannelWatcher {
+    pub fn new(client: &mut DiscordIpcClient) -> IpcResult<Self> {
+        let (changes_tx, changes_rx) = mpsc::channel();
+        let (selected_tx, selected_rx) = mpsc::channel();
+        let select_subscription = client.on_event(Event::VoiceChannelSelect, move |payload| {
+            if let EventPayload::VoiceChannelSelect(select) = payload {
+                let _ = selected_tx.send(select.channel_id);
+            }
+        })?;
+
+        let mut watcher = Self {
+            model: Arc::new(Mutex::new(WatchedChannel {
+                snapshot: VoiceChannelSnapshot::default(),
+                pending: None,
+            })),
+            changes_tx,
+            changes_rx,
+            selected_rx,
+            channel_subscriptions: Vec::new(),
+            _select_subscription: select_subscription,
+        };
+        let channel_id = client
+            .get_selected_voice_channel()?
+            .map(|channel| channel.id);
+        watcher.watch(client, channel_id)?;
+        Ok(watcher)
+    }
+
+    /// Moves to the channel last reported by `VOICE_CHANNEL_SELECT`, if it changed.
+    pub fn sync(&mut self, client: &mut DiscordIpcClient) -> IpcResult<()> {
+        let Some(channel_id) = self.selected_rx.try_iter().last() else {
+            return Ok(());
+        };
+        if channel_id == self.model.lock().unwrap().snapshot.channel_id {
+            return Ok(());
+        }
+        self.watch(client, channel_id)
+    }
+
+    pub fn snapshot(&self) -> VoiceChannelSnapshot {
+        self.model.lock().unwrap().snapshot.clone()
+    }
+
+    /// Changes to the model since the last call, in the order they happened.
+    pub fn changes(&self) -> mpsc::TryIter<'_, VoiceChange> {
+        self.changes_rx.try_iter()
+    }
+
+    /// Subscribes to the channel before fetching its members, so that no change happening in
+    /// between is missed. The events received until then are held back and applied on top of the
+    /// fetched members.
+    fn watch(
+        &mut self,
+        client: &mut DiscordIpcClient,
+        channel_id: Option<String>,
+    ) -> IpcResult<()> {
+        self.channel_subscriptions.clear();
+        *self.model.lock().unwrap() = WatchedChannel {
+            snapshot: VoiceChannelSnapshot {
+                channel_id: channel_id.clone(),
+                guild_id: None,
+                members: Vec::new(),
+            },
+            pending: channel_id.as_ref().map(|_| Vec::new()),
+        };
+        let _ = self
+            .changes_tx
+            .send(VoiceChange::ChannelChanged(channel_id.clone()));
+
+        let Some(channel_id) = channel_id else {
+            return Ok(());
+        };
+        for ev in [
+            Event::VoiceStateCreate,
+            Event::VoiceStateUpdate,
+            Event::VoiceStateDelete,
+            Event::SpeakingStart,
+            Event::SpeakingStop,
+        ] {
+            let model = Arc::clone(&self.model);
+            let changes = self.changes_tx.clone();
+            let watched_id = channel_id.clone();
+            self.channel_subscriptions.push(client.subscribe_event(
+                ev,
+                json!({ "channel_id": channel_id }),
+                move |payload| {
+                    let mut model = model.lock().unwrap();
+                    let model = &mut *model;
+                    // The dispatcher may still be running this callback after a move to another
+                    // channel.
+                    if model.snapshot.channel_id.as_ref() != Some(&watched_id) {
+                        return;
+                    }
+                    match &mut model.pending {
+                        Some(pending) => pending.push(payload),
+                        None => {
+                            if let Some(change) = apply(&mut model.snapshot, payload) {
+                                let _ = changes.send(change);
+                            }
+                        }
+                    }
+                },
+            )?);
+        }
+
+        let channel = client.get_channel(&channel_id);
+        let mut model = self.model.lock().unwrap();
+        let pending = model.pending.take().unwrap_or_default();
+        let channel = channel?;
+        model.snapshot.guild_id = channel.guild_id;
+        for state in channel.voice_states {
+            let member = VoiceMember::from_voice_state(state);
+            let _ = self
+                .changes_tx
+                .send(VoiceChange::MemberJoined(member.clone()));
+            model.snapshot.members.push(member);
+        }
+        for payload in pending {
+            if let Some(change) = apply(&mut model.snapshot, payload) {
+                let _ = self.changes_tx.send(change);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn apply(model: &mut VoiceChannelSnapshot, payload: EventPayload) -> Option<VoiceChange> {
+    match payload {
+        EventPayload::VoiceStateCreate(state) | EventPayload::VoiceStateUpdate(state) => {
+            let mut member = VoiceMember::from_voice_state(state);
+            match model
+                .members
+                .iter_mut()
+                .find(|known| known.user.id == member.user.id)
+            {
+                Some(known) => {
+                    member.speaking = known.speaking;
+                    *known = member.clone();
+                    Some(VoiceChange::MemberUpdated(member))
+                }
+                None => {
+                    model.members.push(member.clone());
+                    Some(VoiceChange::MemberJoined(member))
+                }
+            }
+        }
+        EventPayload::VoiceStateDelete(state) => {
+            model.members.retain(|known| known.user.id != state.user.id);
+            Some(VoiceChange::MemberLeft(state.user.id))
+        }
+        EventPayload::SpeakingStart(speaking) => set_speaking(model, &speaking.user_id, true)
+            .then_some(VoiceChange::SpeakingStarted(speaking.user_id)),
+        EventPayload::SpeakingStop(speaking) => set_speaking(model, &speaking.user_id, false)
+            .then_some(VoiceChange::SpeakingStopped(speaking.user_id)),
+        _ => None,
+    }
+}
+
+/// Returns `false` if `user_id` isn't a known member or was already in that state.
+fn set_speaking(model: &mut VoiceChannelSnapshot, user_id: &str, speaking: bool) -> bool {
+    match model
+        .members
+        .iter_mut()
+        .find(|member| member.user.id == user_id)
+    {
+        Some(member) if member.speaking != speaking => {
+            member.speaking = speaking;
+            true
+        }
+        _ => false,
+    }
+}